@@ -0,0 +1,382 @@
+use std::fmt::Display;
+use std::fs;
+use std::path::Path;
+
+use num_bigint::BigUint;
+use sha1::{Digest, Sha1};
+
+use crate::Error;
+
+/// Location of the certificate chain on the NAND.
+pub const CERT_SYS_PATH: &str = "/sys/cert.sys";
+
+/// DER prefix of a SHA-1 digest in a PKCS#1 v1.5 signature.
+const SHA1_DIGEST_INFO: [u8; 15] = [
+    0x30, 0x21, 0x30, 0x09, 0x06, 0x05, 0x2B, 0x0E, 0x03, 0x02, 0x1A, 0x05, 0x00, 0x04, 0x14,
+];
+
+/// The signature of a signed blob: a certificate, TMD or ticket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    /// 0x10000 for RSA-4096, 0x10001 for RSA-2048, 0x10002 for ECC.
+    pub signature_type: u32,
+    pub signature: Vec<u8>,
+    /// Chain of certificates that signed the blob, e.g.
+    /// `Root-CA00000001-CP00000004`.
+    pub issuer: String,
+    /// SHA-1 of the signed part of the blob, from the issuer on.
+    pub hash: [u8; 20],
+}
+
+impl Signature {
+    /// The offset of the signed part of a blob, which follows the signature
+    /// type, the signature and its padding to 64 bytes.
+    pub(crate) fn body_offset(bytes: &[u8]) -> Result<usize, Error> {
+        let signature_type =
+            u32::from_be_bytes(bytes.get(0..4).ok_or(Error::ReadError)?.try_into().unwrap());
+
+        match signature_type {
+            // RSA-4096
+            0x10000 => Ok(0x240),
+            // RSA-2048
+            0x10001 => Ok(0x140),
+            // ECC
+            0x10002 => Ok(0x80),
+            _ => Err(Error::ReadError),
+        }
+    }
+
+    /// Reads the signature of a blob whose signed part is `signed_len`
+    /// bytes long.
+    pub(crate) fn read(bytes: &[u8], signed_len: usize) -> Result<Self, Error> {
+        let offset = Self::body_offset(bytes)?;
+        let body = bytes
+            .get(offset..offset + signed_len)
+            .ok_or(Error::ReadError)?;
+
+        let signature_type = u32::from_be_bytes(bytes[0..4].try_into().unwrap());
+        let signature_len = match signature_type {
+            0x10000 => 0x200,
+            0x10001 => 0x100,
+            _ => 0x3C,
+        };
+
+        Ok(Self {
+            signature_type,
+            signature: bytes[4..4 + signature_len].to_owned(),
+            issuer: nul_terminated(&body[..0x40]),
+            hash: Sha1::digest(body).into(),
+        })
+    }
+
+    /// Whether the signature has the shape of a fakesign exploiting the
+    /// trucha bug: a zeroed signature over data whose hash starts with a
+    /// zero byte, which IOS's strncmp-based check accepted.
+    pub fn is_fakesigned(&self) -> bool {
+        self.signature.iter().all(|&b| b == 0) && self.hash[0] == 0
+    }
+}
+
+/// The outcome of checking a signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SignatureStatus {
+    /// Signed by the issuer's key.
+    Valid,
+    /// Fakesigned using the trucha bug; see [`Signature::is_fakesigned`].
+    Fakesigned,
+    /// The signature does not match.
+    Invalid,
+    /// The issuer's certificate is not in the chain.
+    UnknownIssuer,
+}
+
+impl SignatureStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SignatureStatus::Valid => "valid",
+            SignatureStatus::Fakesigned => "fakesigned",
+            SignatureStatus::Invalid => "invalid",
+            SignatureStatus::UnknownIssuer => "unknown issuer",
+        }
+    }
+}
+
+impl Display for SignatureStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The public key of a certificate.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PublicKey {
+    Rsa { modulus: Vec<u8>, exponent: u32 },
+    Ecc(Vec<u8>),
+}
+
+/// A certificate of the Wii's chain of trust, e.g. `CP00000004`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Certificate {
+    pub signature: Signature,
+    /// Name of the certificate, e.g. `CP00000004`.
+    pub name: String,
+    pub key: PublicKey,
+    /// Size of the certificate, signature included.
+    pub size: usize,
+}
+
+impl Certificate {
+    /// Decodes a certificate. Data after the certificate is ignored.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let offset = Signature::body_offset(bytes)?;
+        let body = bytes.get(offset..offset + 0x88).ok_or(Error::ReadError)?;
+
+        let key_type = u32::from_be_bytes(body[0x40..0x44].try_into().unwrap());
+        let (modulus_len, key_len) = match key_type {
+            0 => (0x200, 0x238),
+            1 => (0x100, 0x138),
+            2 => (0x3C, 0x78),
+            _ => return Err(Error::ReadError),
+        };
+
+        let signed_len = 0x88 + key_len;
+        let signature = Signature::read(bytes, signed_len)?;
+        let key = &bytes[offset + 0x88..offset + signed_len];
+
+        Ok(Self {
+            signature,
+            name: nul_terminated(&body[0x44..0x84]),
+            key: if key_type == 2 {
+                PublicKey::Ecc(key[..modulus_len].to_owned())
+            } else {
+                PublicKey::Rsa {
+                    modulus: key[..modulus_len].to_owned(),
+                    exponent: u32::from_be_bytes(
+                        key[modulus_len..modulus_len + 4].try_into().unwrap(),
+                    ),
+                }
+            },
+            size: offset + signed_len,
+        })
+    }
+
+    /// The issuer string of blobs signed by this certificate, e.g.
+    /// `Root-CA00000001-CP00000004`.
+    pub fn full_name(&self) -> String {
+        format!("{}-{}", self.signature.issuer, self.name)
+    }
+}
+
+/// The certificates of cert.sys: the CA, and the CP and XS certificates
+/// that sign TMDs and tickets.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CertChain {
+    pub certificates: Vec<Certificate>,
+}
+
+impl CertChain {
+    /// Decodes a file made of certificates one after the other, such as
+    /// cert.sys.
+    pub fn from_bytes(mut bytes: &[u8]) -> Result<Self, Error> {
+        let mut certificates = vec![];
+
+        while !bytes.is_empty() {
+            let certificate = Certificate::from_bytes(bytes)?;
+            bytes = &bytes[certificate.size..];
+            certificates.push(certificate);
+        }
+
+        Ok(Self { certificates })
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Finds the certificate of an issuer, e.g. `Root-CA00000001-CP00000004`.
+    pub fn find(&self, issuer: &str) -> Option<&Certificate> {
+        self.certificates.iter().find(|c| c.full_name() == issuer)
+    }
+
+    /// Checks a signature against the key of its issuer.
+    pub fn verify(&self, signature: &Signature) -> SignatureStatus {
+        if signature.is_fakesigned() {
+            return SignatureStatus::Fakesigned;
+        }
+
+        let Some(certificate) = self.find(&signature.issuer) else {
+            return SignatureStatus::UnknownIssuer;
+        };
+
+        match &certificate.key {
+            PublicKey::Rsa { modulus, exponent } if modulus.len() == signature.signature.len() => {
+                if rsa_verify(modulus, *exponent, &signature.signature, &signature.hash) {
+                    SignatureStatus::Valid
+                } else {
+                    SignatureStatus::Invalid
+                }
+            }
+            _ => SignatureStatus::Invalid,
+        }
+    }
+}
+
+/// Checks a PKCS#1 v1.5 RSA signature of a SHA-1 hash.
+fn rsa_verify(modulus: &[u8], exponent: u32, signature: &[u8], hash: &[u8; 20]) -> bool {
+    let n = BigUint::from_bytes_be(modulus);
+    let s = BigUint::from_bytes_be(signature);
+
+    if s >= n {
+        return false;
+    }
+
+    let decrypted = s.modpow(&BigUint::from(exponent), &n).to_bytes_be();
+
+    let mut expected = vec![0xFF; modulus.len()];
+    let digest_start = modulus.len() - SHA1_DIGEST_INFO.len() - hash.len();
+    expected[0] = 0x00;
+    expected[1] = 0x01;
+    expected[digest_start - 1] = 0x00;
+    expected[digest_start..digest_start + SHA1_DIGEST_INFO.len()]
+        .copy_from_slice(&SHA1_DIGEST_INFO);
+    expected[modulus.len() - hash.len()..].copy_from_slice(hash);
+
+    // The leading zero byte is dropped by the conversion.
+    decrypted == expected[1..]
+}
+
+fn nul_terminated(bytes: &[u8]) -> String {
+    let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+    String::from_utf8_lossy(&bytes[..end]).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Modulus and private exponent of an RSA-2048 key made for these tests.
+    const MODULUS: &str = concat!(
+        "a9056a5dd1de2a57531da92faa97a8337be513508a5c3157a081cad037c7dbe9",
+        "7159918958f80f21e4b451cb490addb2851e2b116d45fdb2d371aae780af7cb4",
+        "63bbf8d284deaec3a08d56fee179749faabfd81e1dce521478e93912416794ba",
+        "1f926b1889125d2d56588b63cbf52619240c3ee0624084647786dd07fff5aebf",
+        "b3d4307b06a7989c064553ddf1aa31f1f438ac04bdad6e2351e7c54a80b1b266",
+        "e09d1106d4b48d364623e405df7815d458b8d23834ba575005634652cc034936",
+        "59b639c8cfe8178a13d251ab87df26a9988cd1af64cc79a6cf97af645e9402e2",
+        "ca59bba5b179c680c00b7f754b9e8c08d2168b9aba2c13f47155ee39aa82cfe3",
+    );
+    const PRIVATE_EXPONENT: &str = concat!(
+        "3d78e2402011a0ada4e58f406e577d0dc87c3ea396c6c40d5e2b8673672e31ad",
+        "bc137aea8ca89f7c50bd42586086eaadc05b8207730a37248061816a74339ef3",
+        "cea8316d8374a7f42f62e86687e3d006382850f65719d06170be460afd2daa7a",
+        "6cfc282642c8228a583298f3a15cd64fe37beeac561fa7e7104fc4f8e15f5dff",
+        "f3e68694aac64e1b49ade42b787cd3be01caf3fcd635e50d47e6bd333dbfbb6c",
+        "88e7490565ba81c4cc80972b07145e86fd88501987124ab86568eaff5fc3d3e9",
+        "e40b822418a8325a1bbab422410ad701b9e8c8a5e2e4aecee2e15e0047523160",
+        "72463d22c5b2b36e0c11ac8c2962e2dd18a7594437179d173abba857ce016451",
+    );
+
+    const ISSUER: &str = "Root-CA00000001-CP00000004";
+
+    fn key(hex: &str) -> BigUint {
+        BigUint::parse_bytes(hex.as_bytes(), 16).unwrap()
+    }
+
+    /// A CP certificate holding the test key. Its own signature is not
+    /// checked.
+    fn chain() -> CertChain {
+        let mut cert = vec![0; 0x140 + 0x88 + 0x138];
+        cert[0..4].copy_from_slice(&0x10001u32.to_be_bytes());
+        cert[0x140..0x14F].copy_from_slice(b"Root-CA00000001");
+        cert[0x180..0x184].copy_from_slice(&1u32.to_be_bytes());
+        cert[0x184..0x18E].copy_from_slice(b"CP00000004");
+        cert[0x1C8..0x2C8].copy_from_slice(&key(MODULUS).to_bytes_be());
+        cert[0x2C8..0x2CC].copy_from_slice(&65537u32.to_be_bytes());
+
+        CertChain::from_bytes(&cert).unwrap()
+    }
+
+    /// An RSA-2048 signed blob with a 0x40-byte body after the issuer, and a
+    /// zeroed signature.
+    fn blob(fill: u8) -> Vec<u8> {
+        let mut blob = vec![fill; 0x140 + 0x80];
+        blob[0..4].copy_from_slice(&0x10001u32.to_be_bytes());
+        blob[4..0x140].fill(0);
+        blob[0x140..0x180].fill(0);
+        blob[0x140..0x140 + ISSUER.len()].copy_from_slice(ISSUER.as_bytes());
+        blob
+    }
+
+    /// Signs a blob with the test key, PKCS#1 v1.5 over SHA-1.
+    fn sign(blob: &mut [u8]) {
+        let mut message = vec![0xFF; 0x100];
+        message[0] = 0x00;
+        message[1] = 0x01;
+        message[0x100 - 36] = 0x00;
+        message[0x100 - 35..0x100 - 20].copy_from_slice(&SHA1_DIGEST_INFO);
+        message[0x100 - 20..].copy_from_slice(&Sha1::digest(&blob[0x140..]));
+
+        let signature = BigUint::from_bytes_be(&message)
+            .modpow(&key(PRIVATE_EXPONENT), &key(MODULUS))
+            .to_bytes_be();
+        blob[4 + 0x100 - signature.len()..4 + 0x100].copy_from_slice(&signature);
+    }
+
+    #[test]
+    fn valid_signature_verifies() {
+        let mut blob = blob(0x5A);
+        sign(&mut blob);
+
+        let signature = Signature::read(&blob, 0x80).unwrap();
+        assert_eq!(signature.issuer, ISSUER);
+        assert_eq!(chain().verify(&signature), SignatureStatus::Valid);
+    }
+
+    #[test]
+    fn tampered_body_is_invalid() {
+        let mut blob = blob(0x5A);
+        sign(&mut blob);
+        blob[0x1A0] ^= 1;
+
+        let signature = Signature::read(&blob, 0x80).unwrap();
+        assert_eq!(chain().verify(&signature), SignatureStatus::Invalid);
+    }
+
+    #[test]
+    fn trucha_fakesign_is_detected() {
+        // Brute-force a byte of the body until its SHA-1 starts with zero,
+        // as fakesigning tools do.
+        let blob = (0..=u8::MAX)
+            .map(blob)
+            .find(|b| Sha1::digest(&b[0x140..])[0] == 0)
+            .unwrap();
+
+        let signature = Signature::read(&blob, 0x80).unwrap();
+        assert!(signature.is_fakesigned());
+        assert_eq!(chain().verify(&signature), SignatureStatus::Fakesigned);
+    }
+
+    #[test]
+    fn zeroed_signature_without_zero_hash_is_invalid() {
+        let blob = (0..=u8::MAX)
+            .map(blob)
+            .find(|b| Sha1::digest(&b[0x140..])[0] != 0)
+            .unwrap();
+
+        let signature = Signature::read(&blob, 0x80).unwrap();
+        assert!(!signature.is_fakesigned());
+        assert_eq!(chain().verify(&signature), SignatureStatus::Invalid);
+    }
+
+    #[test]
+    fn unknown_issuer_is_reported() {
+        let mut blob = blob(0x5A);
+        sign(&mut blob);
+
+        let signature = Signature::read(&blob, 0x80).unwrap();
+        assert_eq!(
+            CertChain::default().verify(&signature),
+            SignatureStatus::UnknownIssuer
+        );
+    }
+}
@@ -0,0 +1,207 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::{parse_entries, tmd_path, Error, Keys, Layout, NandSource, Tmd, UID_SYS_PATH};
+
+/// Location of content.map on the NAND.
+pub const CONTENT_MAP_PATH: &str = "/shared1/content.map";
+
+/// Size in bytes of a single content.map record.
+pub const CONTENT_MAP_RECORD_SIZE: usize = 28;
+
+/// A record of content.map: a content shared between titles, stored in
+/// /shared1 under an eight-character name and identified by its SHA-1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SharedContent {
+    /// File name without the `.app` extension, e.g. `00000012`.
+    pub name: [u8; 8],
+    pub hash: [u8; 20],
+}
+
+impl SharedContent {
+    pub fn name(&self) -> String {
+        String::from_utf8_lossy(&self.name).into_owned()
+    }
+
+    /// The path of the content file on the NAND.
+    pub fn path(&self) -> String {
+        format!("/shared1/{}.app", self.name())
+    }
+}
+
+impl From<&[u8; CONTENT_MAP_RECORD_SIZE]> for SharedContent {
+    fn from(value: &[u8; CONTENT_MAP_RECORD_SIZE]) -> Self {
+        Self {
+            name: value[0..8].try_into().unwrap(),
+            hash: value[8..28].try_into().unwrap(),
+        }
+    }
+}
+
+/// Decodes the contents of a content.map file.
+///
+/// Fails with [`Error::Truncated`] if the data is not made of whole records.
+pub fn parse_content_map(bytes: &[u8]) -> Result<Vec<SharedContent>, Error> {
+    let chunks = bytes.chunks_exact(CONTENT_MAP_RECORD_SIZE);
+
+    if !chunks.remainder().is_empty() {
+        return Err(Error::Truncated {
+            offset: bytes.len() - chunks.remainder().len(),
+            trailing: chunks.remainder().len(),
+        });
+    }
+
+    Ok(chunks
+        .map(|chunk| {
+            SharedContent::from(<&[u8; CONTENT_MAP_RECORD_SIZE]>::try_from(chunk).unwrap())
+        })
+        .collect())
+}
+
+/// A shared content and the titles whose TMD references it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SharedUsage {
+    pub content: SharedContent,
+    /// Title IDs, in the order the TMDs were given.
+    pub titles: Vec<u64>,
+}
+
+impl SharedUsage {
+    /// Whether no title references the content.
+    pub fn is_orphaned(&self) -> bool {
+        self.titles.is_empty()
+    }
+}
+
+/// Finds the titles referencing each shared content, matching the hashes of
+/// the shared contents listed in their TMDs.
+pub fn shared_usage(map: &[SharedContent], tmds: &[Tmd]) -> Vec<SharedUsage> {
+    let mut users = HashMap::<[u8; 20], Vec<u64>>::new();
+
+    for tmd in tmds {
+        for content in tmd.contents.iter().filter(|c| c.is_shared()) {
+            let titles = users.entry(content.hash).or_default();
+
+            if !titles.contains(&tmd.title_id) {
+                titles.push(tmd.title_id);
+            }
+        }
+    }
+
+    map.iter()
+        .map(|&content| SharedUsage {
+            content,
+            titles: users.get(&content.hash).cloned().unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Reads content.map from a file, or from a NAND dump or extracted NAND
+/// together with the TMDs of the titles in its uid.sys that have one. `keys`
+/// are only used for NAND dumps, see [`NandSource::open`].
+pub fn load_shared(
+    path: impl AsRef<Path>,
+    keys: Option<Keys>,
+) -> Result<(Vec<SharedContent>, Option<Vec<Tmd>>), Error> {
+    let path = path.as_ref();
+    let is_nand = path.is_dir() || Layout::detect(fs::metadata(path)?.len()).is_some();
+
+    if !is_nand {
+        return Ok((parse_content_map(&fs::read(path)?)?, None));
+    }
+
+    let mut nand = NandSource::open(path, keys)?;
+    let map = parse_content_map(&nand.read_file(CONTENT_MAP_PATH)?)?;
+    let entries = parse_entries(&nand.read_file(UID_SYS_PATH)?)?;
+
+    let tmds = entries
+        .iter()
+        .filter_map(|e| {
+            nand.read_file(&tmd_path(e.title_id))
+                .and_then(|bytes| Tmd::from_bytes(&bytes))
+                .ok()
+        })
+        .collect();
+
+    Ok((map, Some(tmds)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Signature, TmdContent};
+
+    fn record(name: &[u8; 8], hash: u8) -> Vec<u8> {
+        [&name[..], &[hash; 20]].concat()
+    }
+
+    fn tmd(title_id: u64, shared_hashes: &[u8]) -> Tmd {
+        Tmd {
+            signature: Signature {
+                signature_type: 0x10001,
+                signature: vec![],
+                issuer: String::new(),
+                hash: [0; 20],
+            },
+            sys_version: 0,
+            title_id,
+            title_type: 1,
+            group_id: 0,
+            region: 0,
+            access_rights: 0,
+            title_version: 0,
+            boot_index: 0,
+            contents: shared_hashes
+                .iter()
+                .map(|&hash| TmdContent {
+                    id: 0,
+                    index: 0,
+                    content_type: 0x8001,
+                    size: 0,
+                    hash: [hash; 20],
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn decodes_records() {
+        let bytes = [record(b"00000000", 1), record(b"00000001", 2)].concat();
+        let map = parse_content_map(&bytes).unwrap();
+
+        assert_eq!(map.len(), 2);
+        assert_eq!(map[1].name(), "00000001");
+        assert_eq!(map[1].path(), "/shared1/00000001.app");
+        assert_eq!(map[1].hash, [2; 20]);
+    }
+
+    #[test]
+    fn partial_record_is_truncation() {
+        let mut bytes = record(b"00000000", 1);
+        bytes.extend(b"0000");
+
+        assert!(matches!(
+            parse_content_map(&bytes),
+            Err(Error::Truncated {
+                offset: CONTENT_MAP_RECORD_SIZE,
+                trailing: 4
+            })
+        ));
+    }
+
+    #[test]
+    fn usage_lists_referencing_titles() {
+        let bytes = [record(b"00000000", 1), record(b"00000001", 2)].concat();
+        let map = parse_content_map(&bytes).unwrap();
+        let tmds = [
+            tmd(0x00000001_00000002, &[1, 1]),
+            tmd(0x00010002_48414141, &[1]),
+        ];
+
+        let usage = shared_usage(&map, &tmds);
+
+        assert_eq!(usage[0].titles, [0x00000001_00000002, 0x00010002_48414141]);
+        assert!(usage[1].is_orphaned());
+    }
+}
@@ -0,0 +1,124 @@
+use std::fmt::Display;
+
+use sha1::{Digest, Sha1};
+
+use crate::{Error, SharedContent, Tmd, TmdContent};
+
+/// The state of a content file compared to its TMD record.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ContentStatus {
+    Ok,
+    /// The file does not exist, or a shared content is not in content.map.
+    Missing,
+    /// The file is shorter than the size recorded in the TMD.
+    Truncated {
+        size: u64,
+    },
+    /// The SHA-1 of the file does not match the TMD.
+    Corrupted,
+    /// The file exists but could not be read.
+    Unreadable,
+}
+
+impl Display for ContentStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ContentStatus::Ok => write!(f, "ok"),
+            ContentStatus::Missing => write!(f, "missing"),
+            ContentStatus::Truncated { size } => write!(f, "truncated to {size} bytes"),
+            ContentStatus::Corrupted => write!(f, "SHA-1 mismatch"),
+            ContentStatus::Unreadable => write!(f, "unreadable"),
+        }
+    }
+}
+
+/// The result of checking one content of a title.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContentCheck {
+    pub content: TmdContent,
+    /// Path of the content file on the NAND, if it could be located.
+    pub path: Option<String>,
+    pub status: ContentStatus,
+}
+
+impl ContentCheck {
+    /// Whether the content is damaged. Missing optional contents, such as
+    /// DLC that was never downloaded, are not.
+    pub fn is_problem(&self) -> bool {
+        match self.status {
+            ContentStatus::Ok => false,
+            ContentStatus::Missing => !self.content.is_optional(),
+            _ => true,
+        }
+    }
+}
+
+/// The path of a content file on the NAND: in the title's content directory,
+/// or in /shared1 under the name content.map gives its hash.
+pub fn content_path(title_id: u64, content: &TmdContent, map: &[SharedContent]) -> Option<String> {
+    if content.is_shared() {
+        map.iter()
+            .find(|shared| shared.hash == content.hash)
+            .map(SharedContent::path)
+    } else {
+        Some(format!(
+            "/title/{:08x}/{:08x}/content/{:08x}.app",
+            title_id >> 32,
+            title_id as u32,
+            content.id
+        ))
+    }
+}
+
+/// Checks every content of a title against the SHA-1 recorded in its TMD.
+///
+/// Files are read with `read`, given their absolute NAND path, so that both
+/// NAND dumps and extracted NANDs can be checked.
+pub fn check_contents(
+    tmd: &Tmd,
+    map: &[SharedContent],
+    mut read: impl FnMut(&str) -> Result<Vec<u8>, Error>,
+) -> Vec<ContentCheck> {
+    tmd.contents
+        .iter()
+        .map(|content| {
+            let path = content_path(tmd.title_id, content, map);
+            let status = match &path {
+                Some(path) => check_content(content, read(path)),
+                None => ContentStatus::Missing,
+            };
+
+            ContentCheck {
+                content: *content,
+                path,
+                status,
+            }
+        })
+        .collect()
+}
+
+fn check_content(content: &TmdContent, data: Result<Vec<u8>, Error>) -> ContentStatus {
+    let data = match data {
+        Ok(d) => d,
+        Err(Error::NotFound(_)) => return ContentStatus::Missing,
+        Err(Error::IoError(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+            return ContentStatus::Missing
+        }
+        Err(_) => return ContentStatus::Unreadable,
+    };
+
+    let Some(data) = usize::try_from(content.size)
+        .ok()
+        .and_then(|size| data.get(..size))
+    else {
+        return ContentStatus::Truncated {
+            size: data.len() as u64,
+        };
+    };
+
+    if Sha1::digest(data).as_slice() == content.hash {
+        ContentStatus::Ok
+    } else {
+        ContentStatus::Corrupted
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+
+use serde::Serialize;
+
+use crate::{describe_title, make_gameid_string, Entry, TitleDb, TitleType, Uid};
+
+/// A difference between two uid.sys files.
+///
+/// Positions are counted from 1, as in the listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Change {
+    /// A title only present in the old file.
+    Removed { entry: Entry, position: usize },
+    /// A title only present in the new file.
+    Added { entry: Entry, position: usize },
+    /// A title present in both files with a different UID.
+    UidChanged { title_id: u64, old: Uid, new: Uid },
+    /// A title whose position relative to the other common titles changed.
+    Moved {
+        title_id: u64,
+        old_position: usize,
+        new_position: usize,
+    },
+}
+
+impl Change {
+    pub fn title_id(&self) -> u64 {
+        match self {
+            Change::Removed { entry, .. } | Change::Added { entry, .. } => entry.title_id,
+            Change::UidChanged { title_id, .. } | Change::Moved { title_id, .. } => *title_id,
+        }
+    }
+
+    /// Describes the change in one human-readable line, naming the title
+    /// from `title_db` if given.
+    pub fn describe(&self, title_db: Option<&TitleDb>) -> String {
+        let title = describe_title(self.title_id(), title_db);
+
+        match self {
+            Change::Removed { entry, position } => format!(
+                "- {title}: removed, was entry {position} with UID {}",
+                entry.uid
+            ),
+            Change::Added { entry, position } => format!(
+                "+ {title}: added as entry {position} with UID {}",
+                entry.uid
+            ),
+            Change::UidChanged { old, new, .. } => {
+                format!("~ {title}: UID changed from {old} to {new}")
+            }
+            Change::Moved {
+                old_position,
+                new_position,
+                ..
+            } => format!("> {title}: moved from entry {old_position} to entry {new_position}"),
+        }
+    }
+}
+
+/// Compares two lists of entries.
+///
+/// Changes are returned grouped by kind: removals, additions, UID changes
+/// and finally moves. Moves are kept to a minimum: the largest set of common
+/// titles that kept their relative order is considered unmoved.
+pub fn diff(old: &[Entry], new: &[Entry]) -> Vec<Change> {
+    let old_positions = positions(old);
+    let new_positions = positions(new);
+
+    let mut changes = vec![];
+
+    for (i, entry) in old.iter().enumerate() {
+        if !new_positions.contains_key(&entry.title_id) && old_positions[&entry.title_id] == i {
+            changes.push(Change::Removed {
+                entry: *entry,
+                position: i + 1,
+            });
+        }
+    }
+
+    for (i, entry) in new.iter().enumerate() {
+        if !old_positions.contains_key(&entry.title_id) && new_positions[&entry.title_id] == i {
+            changes.push(Change::Added {
+                entry: *entry,
+                position: i + 1,
+            });
+        }
+    }
+
+    // Common titles in old order, with their position in the new file.
+    let common: Vec<(usize, usize)> = old
+        .iter()
+        .enumerate()
+        .filter(|(i, entry)| old_positions[&entry.title_id] == *i)
+        .filter_map(|(i, entry)| new_positions.get(&entry.title_id).map(|&j| (i, j)))
+        .collect();
+
+    for &(i, j) in &common {
+        if old[i].uid != new[j].uid {
+            changes.push(Change::UidChanged {
+                title_id: old[i].title_id,
+                old: old[i].uid,
+                new: new[j].uid,
+            });
+        }
+    }
+
+    let unmoved = longest_increasing(&common.iter().map(|&(_, j)| j).collect::<Vec<_>>());
+
+    for (k, &(i, j)) in common.iter().enumerate() {
+        if !unmoved.contains(&k) {
+            changes.push(Change::Moved {
+                title_id: old[i].title_id,
+                old_position: i + 1,
+                new_position: j + 1,
+            });
+        }
+    }
+
+    changes
+}
+
+/// Maps each title ID to the index of its first occurrence.
+fn positions(entries: &[Entry]) -> HashMap<u64, usize> {
+    let mut result = HashMap::new();
+
+    for (i, entry) in entries.iter().enumerate() {
+        result.entry(entry.title_id).or_insert(i);
+    }
+
+    result
+}
+
+/// Returns the indices of a longest strictly increasing subsequence.
+fn longest_increasing(values: &[usize]) -> Vec<usize> {
+    let mut length = vec![1; values.len()];
+    let mut previous = vec![None; values.len()];
+
+    for i in 0..values.len() {
+        for j in 0..i {
+            if values[j] < values[i] && length[j] + 1 > length[i] {
+                length[i] = length[j] + 1;
+                previous[i] = Some(j);
+            }
+        }
+    }
+
+    let mut result = vec![];
+    let mut current = (0..values.len()).max_by_key(|&i| length[i]);
+
+    while let Some(i) = current {
+        result.push(i);
+        current = previous[i];
+    }
+
+    result
+}
+
+/// A [`Change`] annotated with the title's type and name, as emitted by the
+/// `diff` subcommand with `--format json`.
+///
+/// `change` is one of `"removed"`, `"added"`, `"uid_changed"` or `"moved"`.
+/// UIDs and positions that do not apply to the change are null.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct DiffRow {
+    pub change: &'static str,
+    pub title_id: String,
+    pub game_id: String,
+    pub title_type: Option<&'static str>,
+    pub name: Option<String>,
+    pub old_uid: Option<u16>,
+    pub new_uid: Option<u16>,
+    pub old_position: Option<usize>,
+    pub new_position: Option<usize>,
+}
+
+impl DiffRow {
+    pub fn new(change: &Change, title_db: Option<&TitleDb>) -> Self {
+        let title_id = change.title_id();
+
+        let mut row = Self {
+            change: "",
+            title_id: format!("{title_id:016X}"),
+            game_id: make_gameid_string(title_id as u32),
+            title_type: TitleType::from_prefix((title_id >> 32) as u32).map(|t| t.name()),
+            name: title_db.and_then(|db| db.name_for_title_id(title_id)),
+            old_uid: None,
+            new_uid: None,
+            old_position: None,
+            new_position: None,
+        };
+
+        match *change {
+            Change::Removed { entry, position } => {
+                row.change = "removed";
+                row.old_uid = Some(entry.uid.0);
+                row.old_position = Some(position);
+            }
+            Change::Added { entry, position } => {
+                row.change = "added";
+                row.new_uid = Some(entry.uid.0);
+                row.new_position = Some(position);
+            }
+            Change::UidChanged { old, new, .. } => {
+                row.change = "uid_changed";
+                row.old_uid = Some(old.0);
+                row.new_uid = Some(new.0);
+            }
+            Change::Moved {
+                old_position,
+                new_position,
+                ..
+            } => {
+                row.change = "moved";
+                row.old_position = Some(old_position);
+                row.new_position = Some(new_position);
+            }
+        }
+
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(title_id: u64, uid: u16) -> Entry {
+        Entry {
+            title_id,
+            padding: 0,
+            uid: Uid(uid),
+        }
+    }
+
+    fn sorted(mut indices: Vec<usize>) -> Vec<usize> {
+        indices.sort();
+        indices
+    }
+
+    #[test]
+    fn longest_increasing_subsequence() {
+        assert_eq!(longest_increasing(&[]), []);
+        assert_eq!(sorted(longest_increasing(&[0, 1, 2])), [0, 1, 2]);
+        assert_eq!(sorted(longest_increasing(&[3, 0, 1, 2])), [1, 2, 3]);
+        assert_eq!(sorted(longest_increasing(&[0, 4, 1, 2, 3])), [0, 2, 3, 4]);
+        assert_eq!(longest_increasing(&[2, 1, 0]).len(), 1);
+    }
+
+    #[test]
+    fn identical_files_have_no_changes() {
+        let entries = [entry(1, 0x1000), entry(2, 0x1001)];
+
+        assert_eq!(diff(&entries, &entries), []);
+    }
+
+    #[test]
+    fn reports_removals_additions_and_moves() {
+        let old = [
+            entry(1, 0x1000),
+            entry(2, 0x1001),
+            entry(3, 0x1002),
+            entry(4, 0x1003),
+        ];
+        // The repeated title 3 is compared by its first occurrence only.
+        let new = [
+            entry(4, 0x1003),
+            entry(1, 0x1000),
+            entry(3, 0x1002),
+            entry(5, 0x1004),
+            entry(3, 0x1005),
+        ];
+
+        assert_eq!(
+            diff(&old, &new),
+            [
+                Change::Removed {
+                    entry: old[1],
+                    position: 2
+                },
+                Change::Added {
+                    entry: new[3],
+                    position: 4
+                },
+                Change::Moved {
+                    title_id: 4,
+                    old_position: 4,
+                    new_position: 1
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn moves_only_the_titles_out_of_order() {
+        let old = [
+            entry(1, 0x1000),
+            entry(2, 0x1001),
+            entry(3, 0x1002),
+            entry(4, 0x1003),
+        ];
+        let new = [
+            entry(2, 0x1001),
+            entry(3, 0x1002),
+            entry(4, 0x1003),
+            entry(1, 0x1004),
+        ];
+
+        assert_eq!(
+            diff(&old, &new),
+            [
+                Change::UidChanged {
+                    title_id: 1,
+                    old: Uid(0x1000),
+                    new: Uid(0x1004)
+                },
+                Change::Moved {
+                    title_id: 1,
+                    old_position: 1,
+                    new_position: 4
+                },
+            ]
+        );
+    }
+}
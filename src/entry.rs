@@ -0,0 +1,271 @@
+use std::fs;
+use std::path::Path;
+
+use crate::nand::Keys;
+use crate::sffs::{replace_nand_file, Sffs};
+use crate::title::{ios_number, make_gameid_string, TitleType};
+use crate::{Error, Uid};
+
+/// Location of uid.sys on the NAND.
+pub const UID_SYS_PATH: &str = "/sys/uid.sys";
+
+/// Size in bytes of a single uid.sys record.
+pub const ENTRY_SIZE: usize = 12;
+
+/// A single record of a uid.sys file.
+///
+/// On disk each record is 12 bytes, big-endian: the 64-bit title ID, two
+/// bytes of padding and the 16-bit UID assigned to the title.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Entry {
+    pub title_id: u64,
+    /// Normally zero, kept so that records can be written back unchanged.
+    pub padding: u16,
+    pub uid: Uid,
+}
+
+impl Entry {
+    /// The upper half of the title ID, which identifies the kind of title.
+    pub fn prefix(&self) -> u32 {
+        (self.title_id >> 32) as u32
+    }
+
+    /// The lower half of the title ID, usually the game ID.
+    pub fn lower_id(&self) -> u32 {
+        self.title_id as u32
+    }
+
+    /// The kind of title according to its prefix, if known.
+    pub fn title_type(&self) -> Option<TitleType> {
+        TitleType::from_prefix(self.prefix())
+    }
+
+    /// The lower ID rendered as ASCII, as done by [`make_gameid_string`].
+    pub fn gameid_string(&self) -> String {
+        make_gameid_string(self.lower_id())
+    }
+
+    /// Encodes the record in its 12-byte on-disk layout.
+    pub fn to_bytes(&self) -> [u8; ENTRY_SIZE] {
+        let mut bytes = [0; ENTRY_SIZE];
+
+        bytes[0..8].copy_from_slice(&self.title_id.to_be_bytes());
+        bytes[8..10].copy_from_slice(&self.padding.to_be_bytes());
+        bytes[10..12].copy_from_slice(&self.uid.0.to_be_bytes());
+
+        bytes
+    }
+
+    /// The position of this title in the installation order, counting from 1.
+    /// Returns `None` if the entry does not have a title UID.
+    pub fn install_number(&self) -> Option<u16> {
+        self.uid.install_number()
+    }
+
+    /// The IOS number if the title is an IOS: a system title with a lower ID
+    /// from 3 to 254. Lower IDs 1 and 2 are boot2 and the System Menu.
+    pub fn ios_number(&self) -> Option<u32> {
+        ios_number(self.title_id)
+    }
+}
+
+impl From<&[u8; ENTRY_SIZE]> for Entry {
+    fn from(value: &[u8; ENTRY_SIZE]) -> Self {
+        Self {
+            title_id: u64::from_be_bytes(value[0..8].try_into().unwrap()),
+            padding: u16::from_be_bytes(value[8..10].try_into().unwrap()),
+            uid: Uid(u16::from_be_bytes(value[10..12].try_into().unwrap())),
+        }
+    }
+}
+
+impl From<&Entry> for [u8; ENTRY_SIZE] {
+    fn from(value: &Entry) -> Self {
+        value.to_bytes()
+    }
+}
+
+/// Trailing data found after the last complete record of a uid.sys file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Truncation {
+    /// Offset of the incomplete record.
+    pub offset: usize,
+    /// Number of bytes in the incomplete record.
+    pub len: usize,
+}
+
+/// The result of decoding a possibly damaged uid.sys file.
+#[derive(Debug, Clone, Default)]
+pub struct Recovered {
+    /// Every complete record in the file.
+    pub entries: Vec<Entry>,
+    /// The incomplete record at the end of the file, if any.
+    pub truncation: Option<Truncation>,
+}
+
+/// Decodes the contents of a uid.sys file.
+///
+/// Fails with [`Error::Truncated`] if the data is not made of whole records.
+/// Use [`parse_entries_lossy`] to recover the complete records instead.
+pub fn parse_entries(bytes: &[u8]) -> Result<Vec<Entry>, Error> {
+    let recovered = parse_entries_lossy(bytes);
+
+    match recovered.truncation {
+        Some(Truncation { offset, len }) => Err(Error::Truncated {
+            offset,
+            trailing: len,
+        }),
+        None => Ok(recovered.entries),
+    }
+}
+
+/// Decodes every complete record of a uid.sys file, reporting any leftover
+/// bytes at the end instead of failing.
+pub fn parse_entries_lossy(bytes: &[u8]) -> Recovered {
+    let chunks = bytes.chunks_exact(ENTRY_SIZE);
+    let remainder = chunks.remainder();
+
+    let truncation = if remainder.is_empty() {
+        None
+    } else {
+        Some(Truncation {
+            offset: bytes.len() - remainder.len(),
+            len: remainder.len(),
+        })
+    };
+
+    let entries = chunks
+        .map(|chunk| Entry::from(<&[u8; ENTRY_SIZE]>::try_from(chunk).unwrap()))
+        .collect();
+
+    Recovered {
+        entries,
+        truncation,
+    }
+}
+
+/// Reads and decodes a uid.sys file.
+pub fn read_entries(path: impl AsRef<Path>) -> Result<Vec<Entry>, Error> {
+    parse_entries(&fs::read(path)?)
+}
+
+/// Reads a uid.sys file, decoding every complete record as done by
+/// [`parse_entries_lossy`].
+pub fn read_entries_lossy(path: impl AsRef<Path>) -> Result<Recovered, Error> {
+    Ok(parse_entries_lossy(&fs::read(path)?))
+}
+
+/// Reads the contents of uid.sys from a BootMii NAND dump, decrypting it.
+///
+/// `keys` must be given unless the dump has a keys.bin block appended.
+pub fn read_uid_sys_from_nand(
+    path: impl AsRef<Path>,
+    keys: Option<Keys>,
+) -> Result<Vec<u8>, Error> {
+    Sffs::open(path, keys)?.read_file(UID_SYS_PATH)
+}
+
+/// Reads and decodes uid.sys from a BootMii NAND dump.
+pub fn read_entries_from_nand(
+    path: impl AsRef<Path>,
+    keys: Option<Keys>,
+) -> Result<Vec<Entry>, Error> {
+    parse_entries(&read_uid_sys_from_nand(path, keys)?)
+}
+
+/// Writes a copy of a BootMii NAND dump to `output`, with its uid.sys
+/// replaced by `entries`. The original dump is left untouched.
+pub fn write_entries_to_nand(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    keys: Option<Keys>,
+    entries: &[Entry],
+) -> Result<(), Error> {
+    replace_nand_file(input, output, keys, UID_SYS_PATH, &encode_entries(entries))
+}
+
+/// Encodes a list of entries into the contents of a uid.sys file.
+///
+/// Decoding and re-encoding a file with [`parse_entries`] and this function
+/// reproduces it byte for byte.
+pub fn encode_entries(entries: &[Entry]) -> Vec<u8> {
+    entries.iter().flat_map(Entry::to_bytes).collect()
+}
+
+/// Writes a list of entries to a uid.sys file, replacing it if it exists.
+pub fn write_entries(path: impl AsRef<Path>, entries: &[Entry]) -> Result<(), Error> {
+    fs::write(path, encode_entries(entries))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The System Menu, an IOS, and a channel with non-zero padding.
+    const UID_SYS: [u8; 3 * ENTRY_SIZE] = [
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x10, 0x00, //
+        0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x3A, 0x00, 0x00, 0x10, 0x01, //
+        0x00, 0x01, 0x00, 0x01, 0x48, 0x41, 0x44, 0x45, 0xAB, 0xCD, 0x10, 0x02, //
+    ];
+
+    #[test]
+    fn encode_reproduces_parsed_file() {
+        let entries = parse_entries(&UID_SYS).unwrap();
+
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[2].title_id, 0x00010001_48414445);
+        assert_eq!(entries[2].padding, 0xABCD);
+        assert_eq!(entries[2].uid, Uid(0x1002));
+        assert_eq!(encode_entries(&entries), UID_SYS);
+    }
+
+    #[test]
+    fn lossy_parse_reports_trailing_partial_record() {
+        let mut bytes = UID_SYS.to_vec();
+        bytes.extend_from_slice(&[0x00, 0x01, 0x00]);
+
+        let recovered = parse_entries_lossy(&bytes);
+
+        assert_eq!(recovered.entries, parse_entries(&UID_SYS).unwrap());
+        assert_eq!(
+            recovered.truncation,
+            Some(Truncation {
+                offset: 3 * ENTRY_SIZE,
+                len: 3
+            })
+        );
+        assert!(matches!(
+            parse_entries(&bytes),
+            Err(Error::Truncated {
+                offset: 36,
+                trailing: 3
+            })
+        ));
+    }
+
+    #[test]
+    fn lossy_parse_keeps_records_before_cut() {
+        let bytes = &UID_SYS[..ENTRY_SIZE + 7];
+
+        let recovered = parse_entries_lossy(bytes);
+
+        assert_eq!(recovered.entries.len(), 1);
+        assert_eq!(recovered.entries[0].title_id, 0x00000001_00000002);
+        assert_eq!(
+            recovered.truncation,
+            Some(Truncation {
+                offset: ENTRY_SIZE,
+                len: 7
+            })
+        );
+    }
+
+    #[test]
+    fn lossy_parse_of_whole_records_has_no_truncation() {
+        let recovered = parse_entries_lossy(&UID_SYS);
+
+        assert_eq!(recovered.entries.len(), 3);
+        assert_eq!(recovered.truncation, None);
+    }
+}
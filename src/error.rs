@@ -0,0 +1,79 @@
+use std::fmt::Display;
+
+/// Errors produced while reading uid.sys files and title databases.
+#[derive(Debug)]
+pub enum Error {
+    /// The underlying file could not be read.
+    IoError(std::io::Error),
+    /// The file was read but its contents are not in the expected format.
+    ReadError,
+    /// A uid.sys file, TMD or content.map ends with an incomplete record.
+    Truncated {
+        /// Offset of the incomplete record.
+        offset: usize,
+        /// Number of bytes left over after the last complete record.
+        trailing: usize,
+    },
+    /// A file does not have the size of a NAND dump.
+    NotNand,
+    /// A NAND dump without appended keys was opened without a keys.bin.
+    MissingKeys,
+    /// A NAND dump has no usable filesystem superblock.
+    NoSuperblock,
+    /// The filesystem of a NAND dump is inconsistent.
+    BadFilesystem,
+    /// A path does not exist on the NAND.
+    NotFound(String),
+    /// There are not enough free clusters on the NAND for a write.
+    NandFull,
+    /// A NAND dump was made without ECC data, which integrity checks need.
+    NoSpareData,
+    /// A cluster of a NAND dump is damaged beyond what ECC can repair.
+    UncorrectableEcc { cluster: u16 },
+    /// A file cluster of a NAND dump does not match its stored HMAC.
+    BadHmac { cluster: u16 },
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::IoError(e) => write!(f, "{e}"),
+            Error::ReadError => write!(f, "File format error"),
+            Error::Truncated { offset, trailing } => write!(
+                f,
+                "File is truncated: {trailing} trailing bytes at offset {offset:#X}"
+            ),
+            Error::NotNand => write!(f, "Not a NAND dump"),
+            Error::MissingKeys => write!(f, "NAND dump has no keys appended, keys.bin needed"),
+            Error::NoSuperblock => write!(f, "No filesystem superblock found in NAND dump"),
+            Error::BadFilesystem => write!(f, "NAND filesystem is corrupt"),
+            Error::NotFound(path) => write!(f, "{path}: not found on NAND"),
+            Error::NandFull => write!(f, "Not enough free space on NAND"),
+            Error::NoSpareData => write!(f, "NAND dump has no ECC data"),
+            Error::UncorrectableEcc { cluster } => {
+                write!(
+                    f,
+                    "NAND cluster {cluster:#06X} has uncorrectable ECC errors"
+                )
+            }
+            Error::BadHmac { cluster } => {
+                write!(f, "NAND cluster {cluster:#06X} does not match its HMAC")
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::IoError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(value: std::io::Error) -> Self {
+        Self::IoError(value)
+    }
+}
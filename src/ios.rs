@@ -0,0 +1,266 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
+use std::io::{self, Write};
+
+use serde::{Serialize, Serializer};
+
+use crate::{classify_ios, describe_title, Entry, IosKind, TitleDb, Tmd};
+
+/// What is known about one IOS slot.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct IosInfo {
+    /// The IOS number, e.g. 58.
+    pub ios: u32,
+    /// Whether the IOS is in uid.sys with a readable TMD.
+    pub installed: bool,
+    pub title_version: Option<u16>,
+    /// What the installed IOS is, if known.
+    pub kind: Option<IosKind>,
+    /// Titles whose TMD requires this IOS, in uid.sys order.
+    #[serde(serialize_with = "title_ids")]
+    pub dependents: Vec<u64>,
+}
+
+impl IosInfo {
+    /// Whether the installed IOS is a Nintendo stub, which cannot run
+    /// anything.
+    pub fn is_stub(&self) -> bool {
+        self.kind == Some(IosKind::Stub)
+    }
+
+    /// Whether a title requires the IOS but it cannot boot it.
+    pub fn is_broken(&self) -> bool {
+        !self.dependents.is_empty() && (!self.installed || self.is_stub())
+    }
+}
+
+impl Display for IosInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "IOS{}", self.ios)?;
+
+        if let Some(version) = self.title_version {
+            write!(f, " v{version}")?;
+        }
+
+        match &self.kind {
+            _ if !self.installed => write!(f, " (missing)"),
+            Some(kind) => write!(f, " ({kind})"),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The IOS each installed title boots, from the sys_version of its TMD.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct IosGraph {
+    /// Every IOS installed or required, by number.
+    pub ios: Vec<IosInfo>,
+    /// Titles of uid.sys, other than IOS, whose TMD could not be read.
+    #[serde(serialize_with = "title_ids")]
+    pub unknown: Vec<u64>,
+    /// IOS of uid.sys whose TMD could not be read, so that neither their
+    /// version nor their kind is known.
+    #[serde(serialize_with = "title_ids")]
+    pub unreadable: Vec<u64>,
+}
+
+impl IosGraph {
+    /// IOS required by at least one title.
+    pub fn required(&self) -> impl Iterator<Item = &IosInfo> {
+        self.ios.iter().filter(|i| !i.dependents.is_empty())
+    }
+
+    /// Installed IOS no title requires, which could be removed.
+    pub fn unused(&self) -> impl Iterator<Item = &IosInfo> {
+        self.ios
+            .iter()
+            .filter(|i| i.installed && i.dependents.is_empty())
+    }
+
+    /// Required IOS that are missing or stubbed.
+    pub fn broken(&self) -> impl Iterator<Item = &IosInfo> {
+        self.ios.iter().filter(|i| i.is_broken())
+    }
+
+    /// Writes the graph in Graphviz DOT format, naming titles from
+    /// `title_db` if given. Missing and stubbed IOS are drawn dashed.
+    pub fn write_dot(&self, mut writer: impl Write, title_db: Option<&TitleDb>) -> io::Result<()> {
+        writeln!(writer, "digraph ios {{")?;
+        writeln!(writer, "    rankdir=LR;")?;
+
+        for ios in &self.ios {
+            let mut label = format!("IOS{}", ios.ios);
+
+            if let Some(version) = ios.title_version {
+                label.push_str(&format!(" v{version}"));
+            }
+
+            let style = if !ios.installed {
+                label.push_str("\\n(missing)");
+                ", style=dashed"
+            } else if ios.is_stub() {
+                label.push_str("\\n(stub)");
+                ", style=dashed"
+            } else {
+                if let Some(kind @ IosKind::Custom(_)) = &ios.kind {
+                    label.push_str(&format!("\\n({})", dot_escape(&kind.to_string())));
+                }
+
+                ""
+            };
+
+            writeln!(
+                writer,
+                "    ios{} [shape=box, label=\"{label}\"{style}];",
+                ios.ios
+            )?;
+        }
+
+        for ios in &self.ios {
+            for &title_id in &ios.dependents {
+                writeln!(
+                    writer,
+                    "    \"{:016X}\" [label=\"{}\"];",
+                    title_id,
+                    dot_escape(&describe_title(title_id, title_db))
+                )?;
+                writeln!(writer, "    \"{title_id:016X}\" -> ios{};", ios.ios)?;
+            }
+        }
+
+        writeln!(writer, "}}")
+    }
+}
+
+/// Builds the IOS dependency graph of the titles in uid.sys from their TMDs
+/// and the kinds of the installed IOS, both given by title ID. IOS whose
+/// kind is not given are classified from their TMD alone.
+pub fn ios_graph(
+    entries: &[Entry],
+    tmds: &HashMap<u64, Tmd>,
+    kinds: &HashMap<u64, IosKind>,
+) -> IosGraph {
+    let mut ios = BTreeMap::<u32, IosInfo>::new();
+    let mut graph = IosGraph::default();
+
+    for entry in entries {
+        let tmd = tmds.get(&entry.title_id);
+
+        if let Some(number) = entry.ios_number() {
+            let info = slot(&mut ios, number);
+
+            if let Some(tmd) = tmd {
+                info.installed = true;
+                info.title_version = Some(tmd.title_version);
+                info.kind = Some(
+                    kinds
+                        .get(&entry.title_id)
+                        .cloned()
+                        .unwrap_or_else(|| classify_ios(number, tmd, None)),
+                );
+            } else {
+                graph.unreadable.push(entry.title_id);
+            }
+
+            continue;
+        }
+
+        match tmd {
+            Some(tmd) => {
+                if let Some(number) = tmd.required_ios() {
+                    let dependents = &mut slot(&mut ios, number).dependents;
+
+                    if !dependents.contains(&entry.title_id) {
+                        dependents.push(entry.title_id);
+                    }
+                }
+            }
+            None => graph.unknown.push(entry.title_id),
+        }
+    }
+
+    graph.ios = ios.into_values().collect();
+    graph
+}
+
+fn slot(ios: &mut BTreeMap<u32, IosInfo>, number: u32) -> &mut IosInfo {
+    ios.entry(number).or_insert_with(|| IosInfo {
+        ios: number,
+        installed: false,
+        title_version: None,
+        kind: None,
+        dependents: vec![],
+    })
+}
+
+fn dot_escape(s: &str) -> String {
+    s.replace('"', "\\\"")
+}
+
+fn title_ids<S: Serializer>(title_ids: &[u64], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(title_ids.iter().map(|id| format!("{id:016X}")))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Signature, Uid};
+
+    fn entry(title_id: u64, uid: u16) -> Entry {
+        Entry {
+            title_id,
+            padding: 0,
+            uid: Uid(uid),
+        }
+    }
+
+    fn tmd(title_id: u64, sys_version: u64, title_version: u16) -> Tmd {
+        Tmd {
+            signature: Signature {
+                signature_type: 0x10001,
+                signature: vec![],
+                issuer: String::new(),
+                hash: [0; 20],
+            },
+            sys_version,
+            title_id,
+            title_type: 1,
+            group_id: 0,
+            region: 0,
+            access_rights: 0,
+            title_version,
+            boot_index: 0,
+            contents: vec![],
+        }
+    }
+
+    #[test]
+    fn sorts_ios_into_required_unused_and_unreadable() {
+        let entries = [
+            entry(0x00000001_00000002, 0x1000),
+            entry(0x00000001_0000003A, 0x1001),
+            entry(0x00000001_00000024, 0x1002),
+            entry(0x00000001_00000015, 0x1003),
+            entry(0x00010001_48414345, 0x1004),
+            entry(0x00010001_48414346, 0x1005),
+        ];
+        let tmds: HashMap<u64, Tmd> = [
+            tmd(0x00000001_00000002, 0x00000001_0000003A, 513),
+            tmd(0x00000001_0000003A, 0, 6176),
+            tmd(0x00000001_00000024, 0, 3608),
+            tmd(0x00010001_48414345, 0x00000001_00000038, 1),
+        ]
+        .into_iter()
+        .map(|t| (t.title_id, t))
+        .collect();
+
+        let graph = ios_graph(&entries, &tmds, &HashMap::new());
+
+        let numbers = |ios: Vec<&IosInfo>| ios.iter().map(|i| i.ios).collect::<Vec<_>>();
+        assert_eq!(numbers(graph.required().collect()), [56, 58]);
+        assert_eq!(numbers(graph.unused().collect()), [36]);
+        assert_eq!(numbers(graph.broken().collect()), [56]);
+        assert_eq!(graph.unreadable, [0x00000001_00000015]);
+        assert_eq!(graph.unknown, [0x00010001_48414346]);
+    }
+}
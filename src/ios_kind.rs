@@ -0,0 +1,346 @@
+use std::fmt::Display;
+
+use serde::Serialize;
+
+use crate::Tmd;
+
+/// Magic number of the info block d2x and later cIOS place at the start of
+/// their first content.
+const IOS_INFO_MAGIC: u32 = 0x1EE7C105;
+
+/// Slots custom IOS are usually installed to.
+const CIOS_SLOTS: [u32; 12] = [202, 222, 223, 224, 225, 245, 246, 247, 248, 249, 250, 251];
+
+/// Retail versions of each genuine Nintendo IOS still in use, oldest first,
+/// so that the last one is the latest.
+const RELEASES: [(u32, &[u16]); 30] = [
+    (9, &[520, 521, 778, 1034]),
+    (12, &[6, 11, 12, 269, 525, 526]),
+    (13, &[10, 15, 16, 273, 1031, 1032]),
+    (14, &[262, 263, 520, 1031, 1032]),
+    (15, &[257, 258, 259, 260, 265, 266, 523, 1031, 1032]),
+    (17, &[512, 517, 518, 775, 1031, 1032]),
+    (21, &[514, 515, 516, 517, 522, 525, 782, 1038, 1039]),
+    (22, &[777, 780, 1037, 1293, 1294]),
+    (28, &[1292, 1293, 1550, 1806, 1807]),
+    (31, &[1037, 1039, 1040, 2576, 3088, 3092, 3349, 3607, 3608]),
+    (33, &[1040, 2832, 2834, 3091, 3607, 3608]),
+    (34, &[1039, 3087, 3091, 3348, 3607, 3608]),
+    (35, &[1040, 3088, 3092, 3349, 3607, 3608]),
+    (36, &[1042, 3090, 3094, 3351, 3607, 3608]),
+    (37, &[2070, 3609, 3612, 3869, 5662, 5663]),
+    (38, &[3610, 3867, 4123, 4124]),
+    (41, &[2835, 3091, 3348, 3606, 3607]),
+    (43, &[2835, 3091, 3348, 3606, 3607]),
+    (45, &[2835, 3091, 3348, 3606, 3607]),
+    (46, &[2837, 3093, 3350, 3606, 3607]),
+    (48, &[4123, 4124]),
+    (53, &[4113, 5149, 5406, 5662, 5663]),
+    (55, &[4633, 5149, 5406, 5662, 5663]),
+    (56, &[4890, 5146, 5405, 5661]),
+    (57, &[5404, 5661, 5918]),
+    (58, &[6175, 6176]),
+    (59, &[8737, 9249]),
+    (61, &[4890, 5405, 5661, 5662]),
+    (62, &[6430]),
+    (80, &[6943, 6944]),
+];
+
+/// Versions of the stubs Nintendo released to replace IOS.
+const STUB_VERSIONS: [(u32, u16); 18] = [
+    (3, 65280),
+    (4, 65280),
+    (10, 768),
+    (11, 256),
+    (16, 512),
+    (20, 256),
+    (30, 2816),
+    (40, 3072),
+    (50, 5120),
+    (51, 4864),
+    (52, 5888),
+    (60, 6400),
+    (70, 6912),
+    (222, 65280),
+    (223, 65280),
+    (249, 65280),
+    (250, 65280),
+    (254, 65280),
+];
+
+/// Most contents a Nintendo stub has; genuine IOS have a dozen or more.
+const STUB_MAX_CONTENTS: usize = 3;
+
+/// Version BootMii uses when installed as IOS254.
+const BOOTMII_VERSION: u16 = 31338;
+
+/// What a custom IOS is known to be.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct CustomIos {
+    /// Name of the cIOS, e.g. `d2x`, when it identifies itself.
+    pub name: Option<String>,
+    /// The Nintendo IOS the cIOS was built from.
+    pub base: Option<u32>,
+    /// Revision, e.g. `v10 beta53-alt` or `rev21`.
+    pub revision: Option<String>,
+}
+
+/// What an installed IOS is.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum IosKind {
+    /// A Nintendo IOS, with the retail release its version is, counting
+    /// from 1, and the latest retail version of its slot if known.
+    Genuine {
+        release: Option<usize>,
+        latest: Option<u16>,
+    },
+    /// A stub released by Nintendo to replace an IOS, which cannot run
+    /// anything.
+    Stub,
+    Custom(CustomIos),
+}
+
+impl IosKind {
+    /// A short name: `"genuine"`, `"stub"` or `"custom"`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            IosKind::Genuine { .. } => "genuine",
+            IosKind::Stub => "stub",
+            IosKind::Custom(_) => "custom",
+        }
+    }
+
+    pub fn base(&self) -> Option<u32> {
+        match self {
+            IosKind::Custom(custom) => custom.base,
+            _ => None,
+        }
+    }
+}
+
+impl Display for IosKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IosKind::Genuine {
+                release: Some(release),
+                latest: Some(latest),
+            } => write!(f, "genuine release {release}, latest v{latest}"),
+            IosKind::Genuine {
+                release: None,
+                latest: Some(latest),
+            } => write!(f, "genuine, unknown version, latest v{latest}"),
+            IosKind::Genuine { .. } => write!(f, "genuine"),
+            IosKind::Stub => write!(f, "Nintendo stub"),
+            IosKind::Custom(custom) => {
+                write!(f, "cIOS")?;
+
+                if let Some(name) = &custom.name {
+                    write!(f, " {name}")?;
+                }
+
+                if let Some(revision) = &custom.revision {
+                    write!(f, " {revision}")?;
+                }
+
+                if let Some(base) = custom.base {
+                    write!(f, ", base IOS{base}")?;
+                }
+
+                Ok(())
+            }
+        }
+    }
+}
+
+/// Classifies an installed IOS from its number, TMD and, if available, the
+/// data of its first content.
+///
+/// cIOS are recognized by the info block d2x and later cIOS carry, which
+/// names their base IOS and revision, or else by their slot: anything but a
+/// Nintendo stub in a usual cIOS slot is taken for a cIOS, and small
+/// versions are read as Waninkoko revisions. Unknown versions with a zero
+/// minor number and only a few contents are taken for stubs, as the known
+/// Nintendo stubs all look like that.
+pub fn classify_ios(ios: u32, tmd: &Tmd, first_content: Option<&[u8]>) -> IosKind {
+    let version = tmd.title_version;
+
+    if let Some(custom) = first_content.and_then(read_ios_info) {
+        return IosKind::Custom(custom);
+    }
+
+    if STUB_VERSIONS.contains(&(ios, version)) {
+        return IosKind::Stub;
+    }
+
+    if ios == 254 && version == BOOTMII_VERSION {
+        return IosKind::Custom(CustomIos {
+            name: Some("BootMii".to_owned()),
+            base: None,
+            revision: None,
+        });
+    }
+
+    if CIOS_SLOTS.contains(&ios) {
+        return IosKind::Custom(CustomIos {
+            name: None,
+            base: None,
+            revision: (version < 100).then(|| format!("rev{version}")),
+        });
+    }
+
+    if version & 0xFF == 0 && tmd.contents.len() <= STUB_MAX_CONTENTS {
+        return IosKind::Stub;
+    }
+
+    IosKind::Genuine {
+        release: ios_release(ios, version),
+        latest: latest_ios_version(ios),
+    }
+}
+
+/// The latest retail version of a genuine Nintendo IOS, if known.
+pub fn latest_ios_version(ios: u32) -> Option<u16> {
+    releases(ios)?.last().copied()
+}
+
+/// Which retail release of a genuine Nintendo IOS a version is, counting
+/// from 1, if known.
+pub fn ios_release(ios: u32, version: u16) -> Option<usize> {
+    releases(ios)?
+        .iter()
+        .position(|&v| v == version)
+        .map(|i| i + 1)
+}
+
+fn releases(ios: u32) -> Option<&'static [u16]> {
+    RELEASES
+        .iter()
+        .find(|(number, _)| *number == ios)
+        .map(|&(_, versions)| versions)
+}
+
+/// Reads the info block of a cIOS:
+/// magic, size, version and base IOS as big-endian u32, then a 16-byte name
+/// and a 16-byte version string.
+fn read_ios_info(data: &[u8]) -> Option<CustomIos> {
+    let u32_at = |offset: usize| {
+        data.get(offset..offset + 4)
+            .map(|b| u32::from_be_bytes(b.try_into().unwrap()))
+    };
+
+    if u32_at(0)? != IOS_INFO_MAGIC {
+        return None;
+    }
+
+    let text = |range: std::ops::Range<usize>| {
+        let bytes = data.get(range)?;
+        let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+        let text = String::from_utf8_lossy(&bytes[..end]).into_owned();
+        (!text.is_empty()).then_some(text)
+    };
+
+    let version = u32_at(8)?;
+    let revision = match text(0x20..0x30) {
+        Some(suffix) => format!("v{version} {suffix}"),
+        None => format!("v{version}"),
+    };
+
+    Some(CustomIos {
+        name: text(0x10..0x20),
+        base: Some(u32_at(12)?),
+        revision: Some(revision),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Signature, TmdContent};
+
+    fn tmd(title_version: u16, content_count: usize) -> Tmd {
+        Tmd {
+            signature: Signature {
+                signature_type: 0x10001,
+                signature: vec![],
+                issuer: String::new(),
+                hash: [0; 20],
+            },
+            sys_version: 0,
+            title_id: 0,
+            title_type: 1,
+            group_id: 0,
+            region: 0,
+            access_rights: 0,
+            title_version,
+            boot_index: 0,
+            contents: (0..content_count as u16)
+                .map(|i| TmdContent {
+                    id: i as u32,
+                    index: i,
+                    content_type: 1,
+                    size: 0,
+                    hash: [0; 20],
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn maps_versions_to_releases() {
+        assert_eq!(
+            classify_ios(36, &tmd(3351, 20), None),
+            IosKind::Genuine {
+                release: Some(4),
+                latest: Some(3608)
+            }
+        );
+        assert_eq!(
+            classify_ios(36, &tmd(3333, 20), None),
+            IosKind::Genuine {
+                release: None,
+                latest: Some(3608)
+            }
+        );
+        assert_eq!(ios_release(58, 6176), Some(2));
+        assert_eq!(ios_release(100, 1), None);
+    }
+
+    #[test]
+    fn stubs_have_few_contents() {
+        assert_eq!(classify_ios(60, &tmd(6400, 3), None), IosKind::Stub);
+        assert_eq!(classify_ios(37, &tmd(5888, 3), None), IosKind::Stub);
+        // A zero minor version alone does not make a stub.
+        assert_eq!(
+            classify_ios(37, &tmd(5888, 20), None),
+            IosKind::Genuine {
+                release: None,
+                latest: Some(5663)
+            }
+        );
+    }
+
+    #[test]
+    fn recognizes_cios() {
+        let mut info = vec![0; 0x30];
+        info[..4].copy_from_slice(&IOS_INFO_MAGIC.to_be_bytes());
+        info[8..12].copy_from_slice(&10u32.to_be_bytes());
+        info[12..16].copy_from_slice(&56u32.to_be_bytes());
+        info[0x10..0x13].copy_from_slice(b"d2x");
+        info[0x20..0x27].copy_from_slice(b"beta53-");
+
+        assert_eq!(
+            classify_ios(249, &tmd(65535, 20), Some(&info)),
+            IosKind::Custom(CustomIos {
+                name: Some("d2x".to_owned()),
+                base: Some(56),
+                revision: Some("v10 beta53-".to_owned()),
+            })
+        );
+        assert_eq!(
+            classify_ios(250, &tmd(21, 20), None).to_string(),
+            "cIOS rev21"
+        );
+        assert_eq!(classify_ios(249, &tmd(65280, 3), None), IosKind::Stub);
+    }
+}
@@ -0,0 +1,67 @@
+//! Decoding of the Wii's `uid.sys` file.
+//!
+//! `uid.sys` lives in `/sys` on the Wii NAND and records, in installation
+//! order, every title that has been run or installed on the console together
+//! with the UID IOS assigned to it.
+
+mod cert;
+mod content_map;
+mod contents;
+mod diff;
+mod entry;
+mod error;
+mod ios;
+mod ios_kind;
+mod listing;
+pub mod nand;
+mod nand_check;
+mod nand_info;
+mod ownership;
+mod rebuild;
+pub mod sffs;
+mod source;
+mod system_menu;
+mod ticket;
+mod title;
+mod titledb;
+mod tmd;
+mod tree;
+mod uid;
+mod verify;
+
+pub use cert::{CertChain, Certificate, PublicKey, Signature, SignatureStatus, CERT_SYS_PATH};
+pub use content_map::{
+    load_shared, parse_content_map, shared_usage, SharedContent, SharedUsage, CONTENT_MAP_PATH,
+    CONTENT_MAP_RECORD_SIZE,
+};
+pub use contents::{check_contents, content_path, ContentCheck, ContentStatus};
+pub use diff::{diff, Change, DiffRow};
+pub use entry::{
+    encode_entries, parse_entries, parse_entries_lossy, read_entries, read_entries_from_nand,
+    read_entries_lossy, read_uid_sys_from_nand, write_entries, write_entries_to_nand, Entry,
+    Recovered, Truncation, ENTRY_SIZE, UID_SYS_PATH,
+};
+pub use error::Error;
+pub use ios::{ios_graph, IosGraph, IosInfo};
+pub use ios_kind::{classify_ios, ios_release, latest_ios_version, CustomIos, IosKind};
+pub use listing::{listing, write_delimited, ListingRow};
+pub use nand::{Keys, Layout, NandImage};
+pub use nand_check::{
+    check_nand, BadBlock, BadBlockReason, EccError, FileCheck, NandReport, SuperblockCheck,
+};
+pub use nand_info::NandInfo;
+pub use ownership::{ownership, OwnedFiles, Ownership, UnknownOwner};
+pub use rebuild::{rebuild_from_dir, rebuild_from_sffs, Rebuilt};
+pub use sffs::{FstEntry, Sffs, Superblock};
+pub use source::NandSource;
+pub use system_menu::SystemMenuVersion;
+pub use ticket::{
+    load_tickets, parse_tickets, ticket_path, ticket_report, Limit, NandTickets, Ticket,
+    TicketReport,
+};
+pub use title::{format_title_id, make_gameid_string, TitleType, SYSTEM_MENU_TITLE_ID};
+pub use titledb::{describe_title, TitleDb, TitleInfo};
+pub use tmd::{tmd_path, Tmd, TmdContent, CONTENT_RECORD_SIZE};
+pub use tree::{InstallState, NandTree};
+pub use uid::{Uid, UidKind};
+pub use verify::{verify, Problem};
@@ -0,0 +1,298 @@
+use std::io::{self, Write};
+
+use serde::Serialize;
+
+use crate::{Entry, IosKind, NandTree, TitleDb, Tmd};
+
+/// One row of the entry listing, with every field already decoded.
+///
+/// This is the object emitted for each entry by `--format json`. Its schema
+/// is stable:
+///
+/// | field              | type           | example              |
+/// |--------------------|----------------|----------------------|
+/// | `install_number`   | number or null | `1`                  |
+/// | `uid`              | number         | `4096`               |
+/// | `title_id`         | string         | `"0000000100000002"` |
+/// | `prefix`           | string         | `"00000001"`         |
+/// | `lower_id`         | string         | `"00000002"`         |
+/// | `game_id`          | string         | `"...."`             |
+/// | `title_type`       | string or null | `"SYSTEM ESSENTIAL"` |
+/// | `name`             | string or null | `"IOS 2"`            |
+/// | `name_language`    | string or null | `"EN"`               |
+/// | `region`           | string or null | `"NTSC-U"`           |
+/// | `developer`        | string or null | `"Nintendo"`         |
+/// | `publisher`        | string or null | `"Nintendo"`         |
+/// | `release_date`     | string or null | `"2008-03-09"`       |
+/// | `genre`            | string or null | `"fighting"`         |
+/// | `install_state`    | string or null | `"installed"`        |
+/// | `title_version`    | number or null | `513`                |
+/// | `required_ios`     | number or null | `58`                 |
+/// | `tmd_title_type`   | string or null | `"00000001"`         |
+/// | `group_id`         | string or null | `"3031"`             |
+/// | `access_rights`    | string or null | `"00000001"`         |
+/// | `tmd_signature`    | string or null | `"valid"`            |
+/// | `ticket_signature` | string or null | `"fakesigned"`       |
+/// | `ios_kind`         | string or null | `"custom"`           |
+/// | `ios_base`         | number or null | `56`                 |
+///
+/// `install_number` is null for entries without a title UID, `title_type` for
+/// unknown prefixes and `name` when no title database was given or the title
+/// is not in it. `name_language` is the language code of `name`, if known.
+/// `install_state` is the [`InstallState`](crate::InstallState) name of the
+/// title when an extracted NAND was given. `title_version`, `required_ios`,
+/// `tmd_title_type`, `group_id` and `access_rights` come from the title's
+/// TMD, when one was read; `required_ios` is null for titles not running on
+/// an IOS. `tmd_signature` and `ticket_signature` are the
+/// [`SignatureStatus`](crate::SignatureStatus) names of the title's TMD and
+/// ticket checked against cert.sys, when all were read. `ios_kind` is the
+/// [`IosKind`](crate::IosKind) name of an installed IOS, classified from its
+/// TMD and first content, and `ios_base` the IOS a cIOS is built on, when
+/// known. `region`, `developer`, `publisher`, `release_date` and `genre` are
+/// only known from wiitdb.xml databases. Hexadecimal fields are upper case
+/// and zero-padded.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ListingRow {
+    pub install_number: Option<u16>,
+    pub uid: u16,
+    pub title_id: String,
+    pub prefix: String,
+    pub lower_id: String,
+    pub game_id: String,
+    pub title_type: Option<&'static str>,
+    pub name: Option<String>,
+    pub name_language: Option<String>,
+    pub region: Option<String>,
+    pub developer: Option<String>,
+    pub publisher: Option<String>,
+    pub release_date: Option<String>,
+    pub genre: Option<String>,
+    pub install_state: Option<&'static str>,
+    pub title_version: Option<u16>,
+    pub required_ios: Option<u32>,
+    pub tmd_title_type: Option<String>,
+    pub group_id: Option<String>,
+    pub access_rights: Option<String>,
+    pub tmd_signature: Option<&'static str>,
+    pub ticket_signature: Option<&'static str>,
+    pub ios_kind: Option<&'static str>,
+    pub ios_base: Option<u32>,
+}
+
+impl ListingRow {
+    /// Column names, in the order returned by [`ListingRow::fields`].
+    pub const HEADER: [&'static str; 24] = [
+        "install_number",
+        "uid",
+        "title_id",
+        "prefix",
+        "lower_id",
+        "game_id",
+        "title_type",
+        "name",
+        "name_language",
+        "region",
+        "developer",
+        "publisher",
+        "release_date",
+        "genre",
+        "install_state",
+        "title_version",
+        "required_ios",
+        "tmd_title_type",
+        "group_id",
+        "access_rights",
+        "tmd_signature",
+        "ticket_signature",
+        "ios_kind",
+        "ios_base",
+    ];
+
+    pub fn new(entry: &Entry, title_db: Option<&TitleDb>) -> Self {
+        let info = title_db.and_then(|db| db.info_for(entry));
+
+        Self {
+            install_number: entry.install_number(),
+            uid: entry.uid.0,
+            title_id: format!("{:016X}", entry.title_id),
+            prefix: format!("{:08X}", entry.prefix()),
+            lower_id: format!("{:08X}", entry.lower_id()),
+            game_id: entry.gameid_string(),
+            title_type: entry.title_type().map(|t| t.name()),
+            name: title_db.and_then(|db| db.name_for(entry)),
+            name_language: title_db
+                .and_then(|db| db.language_for(entry))
+                .map(str::to_owned),
+            region: info.and_then(|i| i.region.clone()),
+            developer: info.and_then(|i| i.developer.clone()),
+            publisher: info.and_then(|i| i.publisher.clone()),
+            release_date: info.and_then(|i| i.release_date.clone()),
+            genre: info.and_then(|i| i.genre.clone()),
+            install_state: None,
+            title_version: None,
+            required_ios: None,
+            tmd_title_type: None,
+            group_id: None,
+            access_rights: None,
+            tmd_signature: None,
+            ticket_signature: None,
+            ios_kind: None,
+            ios_base: None,
+        }
+    }
+
+    /// Fills in the fields read from the title's TMD.
+    pub fn with_tmd(self, tmd: &Tmd) -> Self {
+        Self {
+            title_version: Some(tmd.title_version),
+            required_ios: tmd.required_ios(),
+            tmd_title_type: Some(format!("{:08X}", tmd.title_type)),
+            group_id: Some(format!("{:04X}", tmd.group_id)),
+            access_rights: Some(format!("{:08X}", tmd.access_rights)),
+            ..self
+        }
+    }
+
+    /// Fills in the kind of an installed IOS.
+    pub fn with_ios_kind(self, kind: &IosKind) -> Self {
+        Self {
+            ios_kind: Some(kind.name()),
+            ios_base: kind.base(),
+            ..self
+        }
+    }
+
+    /// The row's fields as text, with missing values left empty.
+    pub fn fields(&self) -> [String; 24] {
+        [
+            self.install_number
+                .map(|n| n.to_string())
+                .unwrap_or_default(),
+            self.uid.to_string(),
+            self.title_id.clone(),
+            self.prefix.clone(),
+            self.lower_id.clone(),
+            self.game_id.clone(),
+            self.title_type.unwrap_or_default().to_owned(),
+            self.name.clone().unwrap_or_default(),
+            self.name_language.clone().unwrap_or_default(),
+            self.region.clone().unwrap_or_default(),
+            self.developer.clone().unwrap_or_default(),
+            self.publisher.clone().unwrap_or_default(),
+            self.release_date.clone().unwrap_or_default(),
+            self.genre.clone().unwrap_or_default(),
+            self.install_state.unwrap_or_default().to_owned(),
+            self.title_version
+                .map(|v| v.to_string())
+                .unwrap_or_default(),
+            self.required_ios.map(|v| v.to_string()).unwrap_or_default(),
+            self.tmd_title_type.clone().unwrap_or_default(),
+            self.group_id.clone().unwrap_or_default(),
+            self.access_rights.clone().unwrap_or_default(),
+            self.tmd_signature.unwrap_or_default().to_owned(),
+            self.ticket_signature.unwrap_or_default().to_owned(),
+            self.ios_kind.unwrap_or_default().to_owned(),
+            self.ios_base.map(|v| v.to_string()).unwrap_or_default(),
+        ]
+    }
+}
+
+/// Builds the listing rows for a list of entries, with their install state
+/// if an extracted NAND is given.
+pub fn listing(
+    entries: &[Entry],
+    title_db: Option<&TitleDb>,
+    tree: Option<&NandTree>,
+) -> Vec<ListingRow> {
+    entries
+        .iter()
+        .map(|entry| ListingRow {
+            install_state: tree.map(|t| t.install_state(entry.title_id).name()),
+            ..ListingRow::new(entry, title_db)
+        })
+        .collect()
+}
+
+/// Writes listing rows as delimiter-separated values with a header row, e.g.
+/// CSV with `','` or TSV with `'\t'`.
+///
+/// Fields containing the delimiter, quotes or line breaks are quoted, with
+/// inner quotes doubled, so the output opens cleanly in spreadsheet tools.
+pub fn write_delimited(
+    mut writer: impl Write,
+    rows: &[ListingRow],
+    delimiter: char,
+) -> io::Result<()> {
+    write_record(&mut writer, &ListingRow::HEADER, delimiter)?;
+
+    for row in rows {
+        write_record(&mut writer, &row.fields(), delimiter)?;
+    }
+
+    Ok(())
+}
+
+fn write_record(
+    writer: &mut impl Write,
+    fields: &[impl AsRef<str>],
+    delimiter: char,
+) -> io::Result<()> {
+    let line = fields
+        .iter()
+        .map(|field| quote_field(field.as_ref(), delimiter))
+        .collect::<Vec<_>>()
+        .join(&delimiter.to_string());
+
+    // CRLF line endings, as expected by spreadsheet tools.
+    write!(writer, "{line}\r\n")
+}
+
+fn quote_field(field: &str, delimiter: char) -> String {
+    if field.contains([delimiter, '"', '\r', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Uid;
+
+    #[test]
+    fn quotes_fields_that_need_it() {
+        assert_eq!(quote_field("Wii Sports", ','), "Wii Sports");
+        assert_eq!(
+            quote_field("Wii Sports, Resort", '\t'),
+            "Wii Sports, Resort"
+        );
+        assert_eq!(quote_field("Tennis, Golf", ','), "\"Tennis, Golf\"");
+        assert_eq!(quote_field("Tennis\tGolf", '\t'), "\"Tennis\tGolf\"");
+        assert_eq!(quote_field("\"Wii\" Shop", ','), "\"\"\"Wii\"\" Shop\"");
+        assert_eq!(quote_field("Line\nbreak", ','), "\"Line\nbreak\"");
+        assert_eq!(quote_field("Line\rbreak", ','), "\"Line\rbreak\"");
+    }
+
+    #[test]
+    fn writes_quoted_rows() {
+        let db = TitleDb::from_reader(&b"HACE = Mii \"Maker\", Channel\n"[..]).unwrap();
+        let entry = Entry {
+            title_id: 0x00010001_48414345,
+            padding: 0,
+            uid: Uid(0x1000),
+        };
+        let rows = listing(&[entry], Some(&db), None);
+
+        let mut out = vec![];
+        write_delimited(&mut out, &rows, ',').unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let lines: Vec<_> = out.split_terminator("\r\n").collect();
+
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], ListingRow::HEADER.join(","));
+        assert!(lines[1].starts_with(
+            "1,4096,0001000148414345,00010001,48414345,HACE,DOWNLOADED CHANNEL,\"Mii \"\"Maker\"\", Channel\","
+        ));
+    }
+}
@@ -1,208 +1,1374 @@
 use std::collections::HashMap;
-use std::fmt::Display;
-use std::fs;
-use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
+use std::fs::{self, File};
+use std::io::Write;
 use std::path::Path;
+use std::process::ExitCode;
 
-use clap::Parser;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use uid_reader::sffs::replace_nand_file;
+use uid_reader::{
+    check_contents, check_nand, describe_title, diff, format_title_id, ios_graph, load_shared,
+    load_tickets, make_gameid_string, ownership, parse_content_map, parse_entries,
+    parse_entries_lossy, read_uid_sys_from_nand, rebuild_from_dir, rebuild_from_sffs, shared_usage,
+    ticket_report, tmd_path, verify, write_delimited, write_entries, BadBlockReason, DiffRow,
+    Entry, Error, Keys, Layout, NandInfo, NandSource, NandTickets, NandTree, Problem, Sffs,
+    SystemMenuVersion, TitleDb, TitleInfo, Tmd, CONTENT_MAP_PATH, SYSTEM_MENU_TITLE_ID,
+    UID_SYS_PATH,
+};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = "Decodes a Wii's uid.sys file")]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    #[command(flatten)]
+    list: ListArgs,
+}
+
+#[derive(Args, Debug)]
+struct ListArgs {
     /// Print the type of a particular title according to its prefix
     #[arg(long, short)]
     decode_prefix: bool,
- 
+
+    #[command(flatten)]
+    title_db: TitleDbArg,
+
+    /// Preferred languages for title names, in order, e.g. "FR,EN". Needs a title source with localized names.
+    #[arg(long, short, value_delimiter = ',')]
+    lang: Vec<String>,
+
+    /// Decode every complete record of a truncated file instead of failing
+    #[arg(long, short)]
+    recover: bool,
+
+    /// Output format
+    #[arg(long, short, value_enum, default_value_t = Format::Text)]
+    format: Format,
+
+    #[command(flatten)]
+    keys: KeysArg,
+
+    /// Extracted NAND directory, e.g. Dolphin's Wii user directory. If provided, each title is marked installed, data-only, ticket-only or ghost, and titles on disk missing from uid.sys are listed.
+    #[arg(long)]
+    nand_root: Option<String>,
+
+    /// Also print the details and content table of each title's TMD. Needs a nand.bin or --nand-root.
+    #[arg(long, short)]
+    verbose: bool,
+
+    /// A uid.sys file, or a BootMii nand.bin to read it from
+    #[arg(required = true)]
+    uid_file: Option<String>,
+}
+
+#[derive(Args, Debug)]
+struct KeysArg {
+    /// Path to keys.bin, needed for a nand.bin without appended keys
+    #[arg(long, short)]
+    keys: Option<String>,
+}
+
+impl KeysArg {
+    fn path(&self) -> Option<&str> {
+        self.keys.as_deref()
+    }
+}
+
+#[derive(Args, Debug)]
+struct TitleDbArg {
+    /// Path to a Wii Title Database text file or GameTDB wiitdb.xml. If provided, the name of each title will be printed if known.
     #[arg(long, short)]
-    /// Path to a Wii Title Database text file. If provided, the name of each title will be printed if known.
     title_db: Option<String>,
+}
 
-    uid_file: String,
+impl TitleDbArg {
+    /// Reads the title database if one was given, reporting errors.
+    fn load(&self) -> Option<TitleDb> {
+        load_title_db(self.title_db.as_ref())
+    }
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Format {
+    /// One human-readable line per entry
+    Text,
+    /// An array of objects, one per entry
+    Json,
+    /// Comma-separated values with a header row
+    Csv,
+    /// Tab-separated values with a header row
+    Tsv,
 }
 
-fn main() {
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Check a uid.sys file for structural anomalies. Exits with a non-zero status if any are found.
+    Verify {
+        #[command(flatten)]
+        keys: KeysArg,
+
+        uid_file: String,
+    },
+
+    /// Show the titles added, removed, reassigned or reordered between two uid.sys files
+    Diff {
+        #[command(flatten)]
+        title_db: TitleDbArg,
+
+        /// Output format
+        #[arg(long, short, value_enum, default_value_t = DiffFormat::Text)]
+        format: DiffFormat,
+
+        #[command(flatten)]
+        keys: KeysArg,
+
+        old_file: String,
+        new_file: String,
+    },
+
+    /// Reconstruct uid.sys from the titles installed on a NAND, using file ownership for UIDs
+    Rebuild {
+        #[command(flatten)]
+        keys: KeysArg,
+
+        #[command(flatten)]
+        title_db: TitleDbArg,
+
+        /// Path of the uid.sys to write
+        #[arg(long, short)]
+        output: String,
+
+        /// A BootMii nand.bin, or a directory holding an extracted NAND
+        source: String,
+    },
+
+    /// List the shared contents of content.map and the installed titles referencing each
+    Shared {
+        #[command(flatten)]
+        keys: KeysArg,
+
+        #[command(flatten)]
+        title_db: TitleDbArg,
+
+        /// A BootMii nand.bin, a directory holding an extracted NAND, or a content.map file. Titles are only shown for the first two.
+        source: String,
+    },
+
+    /// Check the contents of every title in uid.sys against the SHA-1 in its TMD. Exits with a non-zero status if any content is missing, truncated or corrupted.
+    Contents {
+        #[command(flatten)]
+        keys: KeysArg,
+
+        #[command(flatten)]
+        title_db: TitleDbArg,
+
+        /// A BootMii nand.bin, or a directory holding an extracted NAND
+        source: String,
+    },
+
+    /// Show which IOS each installed title boots, which IOS are unused and which titles depend on a missing or stubbed IOS
+    Ios {
+        #[command(flatten)]
+        keys: KeysArg,
+
+        #[command(flatten)]
+        title_db: TitleDbArg,
+
+        /// Output format
+        #[arg(long, short, value_enum, default_value_t = IosFormat::Text)]
+        format: IosFormat,
+
+        /// A BootMii nand.bin, or a directory holding an extracted NAND
+        source: String,
+    },
+
+    /// List the tickets of a NAND, flagging titles of uid.sys without a ticket and tickets for titles missing from uid.sys
+    Tickets {
+        #[command(flatten)]
+        keys: KeysArg,
+
+        #[command(flatten)]
+        title_db: TitleDbArg,
+
+        /// A BootMii nand.bin, or a directory holding an extracted NAND
+        source: String,
+    },
+
+    /// Browse the filesystem of a BootMii nand.bin
+    Nand {
+        #[command(subcommand)]
+        command: NandCommand,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum NandCommand {
+    /// List a directory of the NAND filesystem
+    Ls {
+        #[command(flatten)]
+        keys: KeysArg,
+
+        /// List subdirectories recursively
+        #[arg(long, short)]
+        recursive: bool,
+
+        nand_file: String,
+
+        #[arg(default_value = "/")]
+        path: String,
+    },
+
+    /// Write a file of the NAND filesystem to standard output
+    Cat {
+        #[command(flatten)]
+        keys: KeysArg,
+
+        nand_file: String,
+        path: String,
+    },
+
+    /// Write a copy of a nand.bin with an existing file replaced, e.g. /sys/uid.sys
+    Put {
+        #[command(flatten)]
+        keys: KeysArg,
+
+        /// Path of the new nand.bin to write. The original is never modified.
+        #[arg(long, short)]
+        output: String,
+
+        nand_file: String,
+        path: String,
+
+        /// File with the new contents
+        source: String,
+    },
+
+    /// Check the ECC, bad blocks and HMACs of a nand.bin dumped with ECC data. Exits with a non-zero status if any file is damaged.
+    Verify {
+        #[command(flatten)]
+        keys: KeysArg,
+
+        nand_file: String,
+    },
+
+    /// Show the files each UID of the NAND's uid.sys owns, flagging UIDs owning nothing and files owned by UIDs missing from uid.sys
+    Owners {
+        #[command(flatten)]
+        keys: KeysArg,
+
+        #[command(flatten)]
+        title_db: TitleDbArg,
+
+        nand_file: String,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum IosFormat {
+    /// Human-readable report
+    Text,
+    /// An object with every IOS and its dependents
+    Json,
+    /// A Graphviz graph
+    Dot,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum DiffFormat {
+    /// One human-readable line per change
+    Text,
+    /// An array of objects, one per change
+    Json,
+}
+
+fn main() -> ExitCode {
     let args = Cli::parse();
 
-    let entries = match get_entries_from_file(&args.uid_file) {
-        Some(e) => e,
-        None => return
+    match args.command {
+        Some(Command::Verify { keys, uid_file }) => verify_file(&uid_file, keys.path()),
+        Some(Command::Diff {
+            title_db,
+            format,
+            keys,
+            old_file,
+            new_file,
+        }) => diff_files(
+            &old_file,
+            &new_file,
+            keys.path(),
+            title_db.load().as_ref(),
+            format,
+        ),
+        Some(Command::Rebuild {
+            keys,
+            title_db,
+            output,
+            source,
+        }) => rebuild(&source, keys.path(), &output, title_db.load().as_ref()),
+        Some(Command::Shared {
+            keys,
+            title_db,
+            source,
+        }) => shared(&source, keys.path(), title_db.load().as_ref()),
+        Some(Command::Contents {
+            keys,
+            title_db,
+            source,
+        }) => contents(&source, keys.path(), title_db.load().as_ref()),
+        Some(Command::Ios {
+            keys,
+            title_db,
+            format,
+            source,
+        }) => ios(&source, keys.path(), title_db.load().as_ref(), format),
+        Some(Command::Tickets {
+            keys,
+            title_db,
+            source,
+        }) => tickets(&source, keys.path(), title_db.load().as_ref()),
+        Some(Command::Nand { command }) => match command {
+            NandCommand::Ls {
+                keys,
+                recursive,
+                nand_file,
+                path,
+            } => nand_ls(&nand_file, keys.path(), &path, recursive),
+            NandCommand::Cat {
+                keys,
+                nand_file,
+                path,
+            } => nand_cat(&nand_file, keys.path(), &path),
+            NandCommand::Put {
+                keys,
+                output,
+                nand_file,
+                path,
+                source,
+            } => nand_put(&nand_file, keys.path(), &path, &source, &output),
+            NandCommand::Verify { keys, nand_file } => nand_verify(&nand_file, keys.path()),
+            NandCommand::Owners {
+                keys,
+                title_db,
+                nand_file,
+            } => nand_owners(&nand_file, keys.path(), title_db.load().as_ref()),
+        },
+        None => {
+            let list = args.list;
+            let uid_file = list.uid_file.expect("uid_file is required");
+
+            if let Some(root) = &list.nand_root {
+                if !Path::new(root).is_dir() {
+                    if Path::new(root).exists() {
+                        eprintln!("\"{root}\": Not a directory");
+                    } else {
+                        eprintln!("\"{root}\": Directory not found");
+                    }
+                    return ExitCode::FAILURE;
+                }
+            }
+
+            let entries = match get_entries_from_file(&uid_file, list.keys.path(), list.recover) {
+                Some(e) => e,
+                None => return ExitCode::FAILURE,
+            };
+
+            let title_db = list
+                .title_db
+                .load()
+                .map(|db| db.with_languages(list.lang.clone()));
+            let source = match list.nand_root {
+                Some(root) => Some(NandSource::Tree(NandTree::new(root))),
+                None if is_nand_dump(&uid_file) => open_source(&uid_file, list.keys.path()),
+                None => None,
+            };
+            let nand = match source {
+                Some(mut source) => {
+                    let mut nand = NandInfo::load(&mut source, &entries);
+
+                    if let Err(e) = nand.load_signatures(&mut source, &entries) {
+                        eprintln!("\"{uid_file}\": {e}, signatures cannot be checked");
+                    }
+
+                    nand
+                }
+                None => NandInfo::default(),
+            };
+
+            match list.format {
+                Format::Text => print_entries(
+                    &entries,
+                    list.decode_prefix,
+                    title_db.as_ref(),
+                    !list.lang.is_empty(),
+                    &nand,
+                    list.verbose,
+                ),
+                Format::Json => print_entries_json(&entries, title_db.as_ref(), &nand),
+                Format::Csv => print_entries_delimited(&entries, title_db.as_ref(), &nand, ','),
+                Format::Tsv => print_entries_delimited(&entries, title_db.as_ref(), &nand, '\t'),
+            }
+
+            match &nand.tree {
+                Some(tree) => print_missing_titles(
+                    tree,
+                    &entries,
+                    title_db.as_ref(),
+                    matches!(list.format, Format::Text),
+                ),
+                None => ExitCode::SUCCESS,
+            }
+        }
+    }
+}
+
+fn verify_file(file_name: &str, keys: Option<&str>) -> ExitCode {
+    let recovered = match read_uid_sys(file_name, keys) {
+        Ok(bytes) => parse_entries_lossy(&bytes),
+        Err(e) => {
+            report_read_error(file_name, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut problems = vec![];
+
+    if let Some(t) = recovered.truncation {
+        problems.push(Problem::Truncated(t));
+    }
+
+    problems.extend(verify(&recovered.entries));
+
+    if problems.is_empty() {
+        println!(
+            "\"{file_name}\": {} entries, no problems found",
+            recovered.entries.len()
+        );
+        return ExitCode::SUCCESS;
+    }
+
+    for problem in &problems {
+        println!("{problem}");
+    }
+
+    println!("\"{file_name}\": {} problem(s) found", problems.len());
+    ExitCode::FAILURE
+}
+
+fn diff_files(
+    old_file: &str,
+    new_file: &str,
+    keys: Option<&str>,
+    title_db: Option<&TitleDb>,
+    format: DiffFormat,
+) -> ExitCode {
+    let (old, new) = match (
+        get_entries_from_file(old_file, keys, false),
+        get_entries_from_file(new_file, keys, false),
+    ) {
+        (Some(old), Some(new)) => (old, new),
+        _ => return ExitCode::FAILURE,
+    };
+
+    let changes = diff(&old, &new);
+
+    match format {
+        DiffFormat::Text => {
+            if changes.is_empty() {
+                println!("No changes");
+            }
+
+            for change in &changes {
+                println!("{}", change.describe(title_db));
+            }
+        }
+        DiffFormat::Json => {
+            let rows: Vec<_> = changes.iter().map(|c| DiffRow::new(c, title_db)).collect();
+            println!("{}", serde_json::to_string_pretty(&rows).unwrap());
+        }
+    }
+
+    ExitCode::SUCCESS
+}
+
+fn rebuild(source: &str, keys: Option<&str>, output: &str, title_db: Option<&TitleDb>) -> ExitCode {
+    let result = if Path::new(source).is_dir() {
+        rebuild_from_dir(source)
+    } else {
+        let Some(sffs) = open_nand(source, keys) else {
+            return ExitCode::FAILURE;
+        };
+
+        rebuild_from_sffs(sffs.superblock())
+    };
+
+    let rebuilt = match result {
+        Ok(r) => r,
+        Err(e) => {
+            report_read_error(source, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for entry in &rebuilt.entries {
+        let mut line = describe_entry(entry, title_db);
+
+        if rebuilt.assigned.contains(&entry.title_id) {
+            line.push_str(" [new UID, owner unknown]");
+        } else if rebuilt.ambiguous.contains(&entry.title_id) {
+            line.push_str(" [files owned by several UIDs]");
+        }
+
+        println!("{line}");
+    }
+
+    if let Err(e) = write_entries(output, &rebuilt.entries) {
+        report_read_error(output, e);
+        return ExitCode::FAILURE;
+    }
+
+    println!(
+        "\"{output}\": written with {} entries",
+        rebuilt.entries.len()
+    );
+    ExitCode::SUCCESS
+}
+
+fn shared(source: &str, keys: Option<&str>, title_db: Option<&TitleDb>) -> ExitCode {
+    let result = keys
+        .map(Keys::open)
+        .transpose()
+        .and_then(|keys| load_shared(source, keys));
+
+    let (map, tmds) = match result {
+        Ok(r) => r,
+        Err(e) => {
+            report_read_error(source, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let Some(tmds) = tmds else {
+        for content in &map {
+            println!("{}.app {}", content.name(), hex(&content.hash));
+        }
+
+        println!("\"{source}\": {} shared contents", map.len());
+        return ExitCode::SUCCESS;
+    };
+
+    let usage = shared_usage(&map, &tmds);
+
+    for shared in &usage {
+        let users = if shared.is_orphaned() {
+            "orphaned".to_owned()
+        } else {
+            format!("used by {} title(s)", shared.titles.len())
+        };
+
+        println!(
+            "{}.app {} {users}",
+            shared.content.name(),
+            hex(&shared.content.hash)
+        );
+
+        for &title_id in &shared.titles {
+            println!("    {}", describe_title(title_id, title_db));
+        }
+    }
+
+    println!(
+        "\"{source}\": {} shared contents, {} orphaned, {} TMD(s) read",
+        usage.len(),
+        usage.iter().filter(|u| u.is_orphaned()).count(),
+        tmds.len()
+    );
+    ExitCode::SUCCESS
+}
+
+fn contents(source: &str, keys: Option<&str>, title_db: Option<&TitleDb>) -> ExitCode {
+    let Some(mut nand) = open_source(source, keys) else {
+        return ExitCode::FAILURE;
+    };
+
+    let mut read = |path: &str| nand.read_file(path);
+
+    let entries = match read(UID_SYS_PATH).and_then(|b| parse_entries(&b)) {
+        Ok(e) => e,
+        Err(e) => {
+            report_read_error(source, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let map = match read(CONTENT_MAP_PATH).and_then(|b| parse_content_map(&b)) {
+        Ok(m) => m,
+        Err(e) => {
+            eprintln!("\"{source}\": {e}, shared contents cannot be located");
+            vec![]
+        }
+    };
+
+    let mut damaged = 0;
+
+    for entry in &entries {
+        let title = describe_entry(entry, title_db);
+
+        let tmd = match read(&tmd_path(entry.title_id)).and_then(|b| Tmd::from_bytes(&b)) {
+            Ok(t) => t,
+            Err(_) => {
+                println!("{title}: no TMD");
+                continue;
+            }
+        };
+
+        let checks = check_contents(&tmd, &map, &mut read);
+        let problems: Vec<_> = checks.iter().filter(|c| c.is_problem()).collect();
+
+        if problems.is_empty() {
+            println!("{title}: {} contents ok", checks.len());
+            continue;
+        }
+
+        damaged += 1;
+        println!(
+            "{title}: {} of {} contents damaged",
+            problems.len(),
+            checks.len()
+        );
+
+        for check in problems {
+            println!(
+                "    {:08X} (index {}, {}): {}",
+                check.content.id,
+                check.content.index,
+                check
+                    .path
+                    .as_deref()
+                    .unwrap_or("shared, not in content.map"),
+                check.status
+            );
+        }
+    }
+
+    println!(
+        "\"{source}\": {} titles, {damaged} with damaged contents",
+        entries.len()
+    );
+
+    if damaged == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
+
+fn ios(
+    source: &str,
+    keys: Option<&str>,
+    title_db: Option<&TitleDb>,
+    format: IosFormat,
+) -> ExitCode {
+    let Some((entries, nand)) = load_nand(source, keys) else {
+        return ExitCode::FAILURE;
+    };
+
+    let graph = ios_graph(&entries, &nand.tmds, &nand.ios_kinds);
+
+    match format {
+        IosFormat::Json => {
+            println!("{}", serde_json::to_string_pretty(&graph).unwrap());
+            return ExitCode::SUCCESS;
+        }
+        IosFormat::Dot => {
+            graph.write_dot(std::io::stdout().lock(), title_db).unwrap();
+            return ExitCode::SUCCESS;
+        }
+        IosFormat::Text => {}
+    }
+
+    let uids: HashMap<u64, &Entry> = entries.iter().map(|e| (e.title_id, e)).collect();
+    let describe = |title_id: u64| describe_entry(uids[&title_id], title_db);
+
+    println!("Required IOS:");
+
+    for ios in graph.required() {
+        println!("  {ios}: {} title(s)", ios.dependents.len());
+
+        for &title_id in &ios.dependents {
+            println!("      {}", describe(title_id));
+        }
+    }
+
+    println!("Unused IOS:");
+
+    for ios in graph.unused() {
+        println!("  {ios}");
+    }
+
+    for ios in graph.broken() {
+        for &title_id in &ios.dependents {
+            println!("Broken: {} needs {ios}", describe(title_id));
+        }
+    }
+
+    for &title_id in graph.unreadable.iter().chain(&graph.unknown) {
+        println!("No TMD: {}", describe(title_id));
+    }
+
+    println!(
+        "\"{source}\": {} IOS required, {} unused, {} missing or stubbed",
+        graph.required().count(),
+        graph.unused().count(),
+        graph.broken().count()
+    );
+    ExitCode::SUCCESS
+}
+
+/// Reads uid.sys and what [`NandInfo`] knows about its titles from a NAND
+/// dump or extracted NAND, reporting errors.
+fn load_nand(source: &str, keys: Option<&str>) -> Option<(Vec<Entry>, NandInfo)> {
+    let mut nand = open_source(source, keys)?;
+
+    let entries = match nand.read_file(UID_SYS_PATH).and_then(|b| parse_entries(&b)) {
+        Ok(e) => e,
+        Err(e) => {
+            report_read_error(source, e);
+            return None;
+        }
     };
 
-    print_entries(&entries, args.decode_prefix, args.title_db);
+    let nand = NandInfo::load(&mut nand, &entries);
+    Some((entries, nand))
 }
 
-impl Display for Error {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            Error::IoError(e) => write!(f, "{e}"),
-            Error::ReadError => write!(f, "File format error"),
+fn tickets(source: &str, keys: Option<&str>, title_db: Option<&TitleDb>) -> ExitCode {
+    let Some(mut nand) = open_source(source, keys) else {
+        return ExitCode::FAILURE;
+    };
+
+    let result = load_tickets(&mut nand)
+        .and_then(|t| Ok((parse_entries(&nand.read_file(UID_SYS_PATH)?)?, t)));
+
+    let (
+        entries,
+        NandTickets {
+            tickets,
+            unreadable,
+        },
+    ) = match result {
+        Ok(r) => r,
+        Err(e) => {
+            report_read_error(source, e);
+            return ExitCode::FAILURE;
         }
+    };
+
+    for (path, e) in unreadable {
+        eprintln!("\"{path}\": {e}");
     }
+
+    let report = ticket_report(&entries, &tickets);
+
+    for ticket in &tickets {
+        let console = if ticket.is_personalized() {
+            format!("console {:08X}", ticket.console_id)
+        } else {
+            "any console".to_owned()
+        };
+
+        let unused = if report.unused.contains(ticket) {
+            " [not in uid.sys]"
+        } else {
+            ""
+        };
+
+        println!("{}{unused}", describe_title(ticket.title_id, title_db));
+        println!(
+            "    Ticket ID: {:016X} | {console} | Common key: {} | Title key: {}",
+            ticket.ticket_id,
+            ticket.common_key_index,
+            hex(&ticket.encrypted_title_key)
+        );
+
+        for limit in &ticket.limits {
+            match limit.kind {
+                1 => println!("    Limit: {} seconds of play time", limit.value),
+                4 => println!("    Limit: {} launches", limit.value),
+                kind => println!("    Limit: type {kind}, value {}", limit.value),
+            }
+        }
+    }
+
+    for entry in &report.missing {
+        println!("No ticket: {}", describe_entry(entry, title_db));
+    }
+
+    println!(
+        "\"{source}\": {} tickets, {} not in uid.sys, {} title(s) without a ticket",
+        tickets.len(),
+        report.unused.len(),
+        report.missing.len()
+    );
+    ExitCode::SUCCESS
 }
 
-impl std::error::Error for Error {}
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
 
-impl From<std::io::Error> for Error {
-    fn from(value: std::io::Error) -> Self {
-        Self::IoError(value)
+/// Opens an extracted NAND or NAND dump, reporting errors.
+fn open_source(source: &str, keys: Option<&str>) -> Option<NandSource> {
+    let result = keys
+        .map(Keys::open)
+        .transpose()
+        .and_then(|keys| NandSource::open(source, keys));
+
+    match result {
+        Ok(nand) => Some(nand),
+        Err(e) => {
+            report_read_error(source, e);
+            None
+        }
     }
 }
 
-#[derive(Debug)]
-enum Error {
-    IoError(std::io::Error),
-    ReadError,
+fn open_nand(nand_file: &str, keys: Option<&str>) -> Option<Sffs<File>> {
+    let result = keys
+        .map(Keys::open)
+        .transpose()
+        .and_then(|keys| Sffs::open(nand_file, keys));
+
+    match result {
+        Ok(sffs) => Some(sffs),
+        Err(e) => {
+            report_read_error(nand_file, e);
+            None
+        }
+    }
 }
 
-fn read_titledb(path: impl AsRef<Path>) -> Result<HashMap<String, String>, Error> {
-    let file = BufReader::new(File::open(path)?);
-    let mut result = HashMap::<String, String>::new();
+fn nand_ls(nand_file: &str, keys: Option<&str>, path: &str, recursive: bool) -> ExitCode {
+    let Some(sffs) = open_nand(nand_file, keys) else {
+        return ExitCode::FAILURE;
+    };
 
-    for line in file.lines() {
-        let line = line?;
-        let mut entry = line.split(" = ");
+    let superblock = sffs.superblock();
 
-        let title_id;
-        let human_name;
+    let listing = superblock.lookup(path).and_then(|dir| {
+        if !superblock.fst[dir].is_dir() {
+            return Err(Error::NotFound(path.to_owned()));
+        }
 
-        if let (Some(t), Some(h)) = (entry.next(), entry.next()) {
-            title_id = t;
-            human_name = h;
+        if recursive {
+            superblock.walk(dir)
         } else {
-            return Err(Error::ReadError);
+            let prefix = path.trim_end_matches('/');
+
+            Ok(superblock
+                .children(dir)?
+                .into_iter()
+                .map(|i| (format!("{prefix}/{}", superblock.fst[i].name()), i))
+                .collect())
         }
+    });
 
-        result.insert(title_id.to_owned(), human_name.to_owned());
+    let listing = match listing {
+        Ok(l) => l,
+        Err(e) => {
+            report_read_error(nand_file, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for (path, index) in listing {
+        let entry = &superblock.fst[index];
+        println!(
+            "{} {:08X} {:04X} {:>10} {path}",
+            entry.mode_string(),
+            entry.uid,
+            entry.gid,
+            entry.size
+        );
     }
 
-    Ok(result)
+    ExitCode::SUCCESS
 }
 
-fn print_entries(entries: &[Entry], pretty_prefix: bool, title_db_path: Option<impl AsRef<Path>>) {
-    let title_db;
+fn nand_cat(nand_file: &str, keys: Option<&str>, path: &str) -> ExitCode {
+    let Some(mut sffs) = open_nand(nand_file, keys) else {
+        return ExitCode::FAILURE;
+    };
 
-    if let Some(path) = title_db_path {
-        title_db = match read_titledb(path) {
-            Ok(m) => Some(m),
-            Err(e) => {
-                eprintln!("error while reading title database: {e}");
-                None
+    match sffs.read_file(path) {
+        Ok(data) => {
+            if let Err(e) = std::io::stdout().lock().write_all(&data) {
+                eprintln!("error while writing output: {e}");
+                return ExitCode::FAILURE;
             }
+
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            report_read_error(nand_file, e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn nand_put(
+    nand_file: &str,
+    keys: Option<&str>,
+    path: &str,
+    source: &str,
+    output: &str,
+) -> ExitCode {
+    let data = match fs::read(source) {
+        Ok(d) => d,
+        Err(e) => {
+            report_read_error(source, e.into());
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let result = keys
+        .map(Keys::open)
+        .transpose()
+        .and_then(|keys| replace_nand_file(nand_file, output, keys, path, &data));
+
+    match result {
+        Ok(()) => {
+            println!("\"{output}\": written with {path} replaced");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("\"{output}\": {e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn nand_verify(nand_file: &str, keys: Option<&str>) -> ExitCode {
+    let Some(mut sffs) = open_nand(nand_file, keys) else {
+        return ExitCode::FAILURE;
+    };
+
+    let report = match check_nand(&mut sffs) {
+        Ok(r) => r,
+        Err(e) => {
+            report_read_error(nand_file, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    println!(
+        "Pages: {} checked, {} erased",
+        report.pages_checked, report.pages_erased
+    );
+
+    for error in &report.ecc_errors {
+        println!(
+            "ECC: page {:#07X} (cluster {:#06X}) block {}: {}",
+            error.page,
+            error.cluster(),
+            error.subpage,
+            if error.correctable {
+                "correctable single-bit error"
+            } else {
+                "uncorrectable"
+            }
+        );
+    }
+
+    for block in &report.bad_blocks {
+        println!(
+            "Bad block {:#05X}: {}",
+            block.block,
+            match block.reason {
+                BadBlockReason::Marker => "marked bad in spare data",
+                BadBlockReason::Fat => "marked bad in FAT",
+            }
+        );
+    }
+
+    for superblock in &report.superblocks {
+        println!(
+            "Superblock at {:#06X}, generation {}{}: HMAC {}",
+            superblock.cluster,
+            superblock.generation,
+            if superblock.in_use { " (in use)" } else { "" },
+            if superblock.hmac_ok { "ok" } else { "mismatch" }
+        );
+    }
+
+    for file in report.damaged_files() {
+        let clusters = |c: &[u16]| {
+            c.iter()
+                .map(|c| format!("{c:#06X}"))
+                .collect::<Vec<_>>()
+                .join(", ")
         };
+
+        println!("Damaged: {}", file.path);
+
+        if file.bad_chain {
+            println!("    Broken or looping FAT chain");
+        }
+
+        if !file.bad_hmac.is_empty() {
+            println!(
+                "    HMAC mismatch in cluster(s) {}",
+                clusters(&file.bad_hmac)
+            );
+        }
+
+        if !file.bad_ecc.is_empty() {
+            println!(
+                "    Uncorrectable ECC in cluster(s) {}",
+                clusters(&file.bad_ecc)
+            );
+        }
+
+        if !file.bad_blocks.is_empty() {
+            println!("    Bad block in cluster(s) {}", clusters(&file.bad_blocks));
+        }
+    }
+
+    match report.file(UID_SYS_PATH) {
+        Some(file) if file.is_ok() => println!("{UID_SYS_PATH}: ok"),
+        Some(_) => println!("{UID_SYS_PATH}: damaged"),
+        None => println!("{UID_SYS_PATH}: missing"),
+    }
+
+    if report.is_ok() {
+        println!(
+            "\"{nand_file}\": {} files, no problems found",
+            report.files.len()
+        );
+        ExitCode::SUCCESS
     } else {
-        title_db = None;
+        println!(
+            "\"{nand_file}\": {} of {} files damaged",
+            report.damaged_files().count(),
+            report.files.len()
+        );
+        ExitCode::FAILURE
+    }
+}
+
+fn nand_owners(nand_file: &str, keys: Option<&str>, title_db: Option<&TitleDb>) -> ExitCode {
+    let Some(mut sffs) = open_nand(nand_file, keys) else {
+        return ExitCode::FAILURE;
+    };
+
+    let result = sffs
+        .read_file(UID_SYS_PATH)
+        .and_then(|bytes| parse_entries(&bytes))
+        .and_then(|entries| ownership(&entries, sffs.superblock()));
+
+    let ownership = match result {
+        Ok(o) => o,
+        Err(e) => {
+            report_read_error(nand_file, e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    for owned in &ownership.titles {
+        let title = describe_entry(&owned.entry, title_db);
+
+        if owned.paths.is_empty() {
+            println!("{title}: owns nothing");
+            continue;
+        }
+
+        println!("{title}: {} file(s)", owned.paths.len());
+
+        for path in &owned.paths {
+            println!("    {path}");
+        }
+    }
+
+    for unknown in &ownership.unknown {
+        println!(
+            "Owner {:#010X} not in uid.sys: {}",
+            unknown.uid, unknown.path
+        );
+    }
+
+    println!(
+        "\"{nand_file}\": {} UID(s) owning nothing, {} file(s) with an unknown owner",
+        ownership.unused().count(),
+        ownership.unknown.len()
+    );
+
+    ExitCode::SUCCESS
+}
+
+/// Describes an entry in one line: UID, title ID, game ID and name if known.
+fn describe_entry(entry: &Entry, title_db: Option<&TitleDb>) -> String {
+    format!("{} {}", entry.uid, describe_title(entry.title_id, title_db))
+}
+
+fn load_title_db(title_db_path: Option<impl AsRef<Path>>) -> Option<TitleDb> {
+    match TitleDb::open(title_db_path?) {
+        Ok(m) => Some(m),
+        Err(e) => {
+            eprintln!("error while reading title database: {e}");
+            None
+        }
+    }
+}
+
+fn print_entries(
+    entries: &[Entry],
+    pretty_prefix: bool,
+    title_db: Option<&TitleDb>,
+    show_language: bool,
+    nand: &NandInfo,
+    verbose: bool,
+) {
+    if let Some(tmd) = nand.tmds.get(&SYSTEM_MENU_TITLE_ID) {
+        match SystemMenuVersion::from_tmd(tmd) {
+            Some(v) if v.is_vwii() => println!(
+                "System Menu {v} ({}, vWii), v{}",
+                v.region_name(),
+                tmd.title_version
+            ),
+            Some(v) => println!(
+                "System Menu {v} ({}), v{}",
+                v.region_name(),
+                tmd.title_version
+            ),
+            None => println!("System Menu: unknown version v{}", tmd.title_version),
+        }
     }
 
     for entry in entries {
         let title_id_prefix = if pretty_prefix {
-            match (entry.title_id >> 32) as u32 {
-                0x00000001 => "SYSTEM ESSENTIAL",
-                0x00000007 => "vWII ESSENTIAL",
-                0x00010000 => "DISC-BASED GAME",
-                0x00010001 => "DOWNLOADED CHANNEL",
-                0x00010002 => "SYSTEM CHANNEL",
-                0x00070002 => "vWII SYSTEM CHANNEL",
-                0x00010004 => "GAME CHANNEL",
-                0x00010005 => "GAME DLC",
-                0x00010008 => "HIDDEN CHANNEL",
-                0x00070008 => "vWII HIDDEN",
-
-                _ => "Error",
-            }
+            entry.title_type().map_or("Error", |t| t.name())
         } else {
             ""
         };
 
-        let title_id_prefix_raw = format!("{:08X}", (entry.title_id >> 32) as u32);
+        let title_id_prefix_raw = format!("{:08X}", entry.prefix());
 
-        let title_id_gameid_string = make_gameid_string(entry.title_id as u32);
+        let title_id_gameid_string = entry.gameid_string();
 
-        let title_id_gameid_raw = format!("{:08X}", (entry.title_id as u32));
+        let title_id_gameid_raw = format!("{:08X}", entry.lower_id());
 
-        let install_num = entry.uid - 4095;
+        let install_num = match entry.install_number() {
+            Some(n) => n.to_string(),
+            None => format!("!{} ({})", entry.uid, entry.uid.kind()),
+        };
 
-        let title_human_name = match &title_db {
-            Some(title_db) => match title_db.get(title_id_gameid_string.as_str()) {
-                Some(s) => format!(" - {s}"),
-                None => {
-                    if (entry.title_id as u32) < 255 {
-                        format!(" - IOS {}", (entry.title_id as u32))
-                    } else {
-                        " - ????".to_owned()
-                    }
-                }
+        let title_human_name = match title_db {
+            Some(title_db) => match title_db.name_for(entry) {
+                Some(s) => match title_db.language_for(entry) {
+                    Some(lang) if show_language => format!(" - {s} [{lang}]"),
+                    _ => format!(" - {s}"),
+                },
+                None => " - ????".to_owned(),
+            },
+
+            None => "".to_owned(),
+        };
+
+        let install_state = match &nand.tree {
+            Some(tree) => format!(" [{}]", tree.install_state(entry.title_id)),
+            None => "".to_owned(),
+        };
+
+        let tmd = nand.tmds.get(&entry.title_id);
+
+        let signatures = match nand.signatures(entry.title_id) {
+            (Some(tmd), Some(ticket)) => format!(" [TMD {tmd}, ticket {ticket}]"),
+            (Some(tmd), None) if !nand.tickets.contains_key(&entry.title_id) => {
+                format!(" [TMD {tmd}, no ticket]")
+            }
+            (Some(tmd), None) => format!(" [TMD {tmd}]"),
+            (None, Some(ticket)) => format!(" [ticket {ticket}]"),
+            (None, None) => "".to_owned(),
+        };
+
+        let version = match tmd {
+            Some(tmd) => match tmd.required_ios() {
+                Some(ios) => format!(" v{} IOS{ios}", tmd.title_version),
+                None => format!(" v{}", tmd.title_version),
             },
+            None => "".to_owned(),
+        };
 
+        let ios_kind = match nand.ios_kinds.get(&entry.title_id) {
+            Some(kind) => format!(" [{kind}]"),
             None => "".to_owned(),
         };
 
         if pretty_prefix {
-            println!("{install_num}: {: <19}{title_id_prefix_raw}-{title_id_gameid_raw} ({title_id_gameid_string}){title_human_name}", title_id_prefix)
+            println!("{install_num}: {: <19}{title_id_prefix_raw}-{title_id_gameid_raw} ({title_id_gameid_string}){title_human_name}{version}{ios_kind}{install_state}{signatures}", title_id_prefix)
         } else {
-            println!("{install_num}: {title_id_prefix_raw}-{title_id_gameid_raw} ({title_id_gameid_string}){title_human_name}")
+            println!("{install_num}: {title_id_prefix_raw}-{title_id_gameid_raw} ({title_id_gameid_string}){title_human_name}{version}{ios_kind}{install_state}{signatures}")
+        }
+
+        if let Some(info) = title_db.and_then(|db| db.info_for(entry)) {
+            if info.has_metadata() {
+                println!("    {}", describe_metadata(info));
+            }
+        }
+
+        if let (Some(tmd), true) = (tmd, verbose) {
+            print_tmd(tmd);
         }
     }
 }
 
-fn make_gameid_string(gameid: u32) -> String {
-    let bytes = gameid.to_be_bytes();
-
-    let mut result = String::new();
+fn print_tmd(tmd: &Tmd) {
+    println!(
+        "    Type: {:08X} | Group: {:04X} ({}) | Access rights: {:08X} | Boot index: {}",
+        tmd.title_type,
+        tmd.group_id,
+        tmd.group_id_string(),
+        tmd.access_rights,
+        tmd.boot_index
+    );
 
-    for byte in bytes {
-        let character = if (32..128).contains(&byte) {
-            char::from(byte)
-        } else {
-            '.'
-        };
+    println!("    Index ID       Type       Size SHA-1");
 
-        result.push(character);
+    for content in &tmd.contents {
+        println!(
+            "    {:>5} {:08X} {:04X} {:>10} {}",
+            content.index,
+            content.id,
+            content.content_type,
+            content.size,
+            hex(&content.hash)
+        );
     }
+}
 
-    result
+fn describe_metadata(info: &TitleInfo) -> String {
+    let fields = [
+        ("Region", &info.region),
+        ("Developer", &info.developer),
+        ("Publisher", &info.publisher),
+        ("Released", &info.release_date),
+        ("Genre", &info.genre),
+    ];
+
+    fields
+        .iter()
+        .filter_map(|(label, value)| value.as_ref().map(|v| format!("{label}: {v}")))
+        .collect::<Vec<_>>()
+        .join(" | ")
 }
 
-fn get_entries_from_file(file_name: &str) -> Option<Vec<Entry>> {
-    let bytes = match fs::read(file_name) {
-        Ok(v) => v,
-        Err(e) => match e.kind() {
-            std::io::ErrorKind::NotFound => {
-                eprintln!("\"{file_name}\": File not found");
-                return None;
-            }
+fn print_entries_json(entries: &[Entry], title_db: Option<&TitleDb>, nand: &NandInfo) {
+    let rows = nand.rows(entries, title_db);
+    println!("{}", serde_json::to_string_pretty(&rows).unwrap());
+}
 
-            _ => {
-                eprintln!("\"{file_name}\": Error opening file");
-                return None;
-            }
-        },
+fn print_entries_delimited(
+    entries: &[Entry],
+    title_db: Option<&TitleDb>,
+    nand: &NandInfo,
+    delimiter: char,
+) {
+    let rows = nand.rows(entries, title_db);
+    write_delimited(std::io::stdout().lock(), &rows, delimiter).unwrap();
+}
+
+fn is_nand_dump(file_name: &str) -> bool {
+    fs::metadata(file_name).is_ok_and(|m| Layout::detect(m.len()).is_some())
+}
+
+/// Lists titles present in an extracted NAND but missing from uid.sys, on
+/// standard output after a text listing or on standard error otherwise, to
+/// keep machine-readable output intact.
+fn print_missing_titles(
+    tree: &NandTree,
+    entries: &[Entry],
+    title_db: Option<&TitleDb>,
+    to_stdout: bool,
+) -> ExitCode {
+    let missing = match tree.missing_from(entries) {
+        Ok(m) => m,
+        Err(e) => {
+            report_read_error(&tree.root().display().to_string(), e);
+            return ExitCode::FAILURE;
+        }
     };
 
-    let mut entries: Vec<Entry> = vec![];
+    for title_id in missing {
+        let mut line = format!(
+            "On disk but not in uid.sys: {} ({}) [{}]",
+            format_title_id(title_id),
+            make_gameid_string(title_id as u32),
+            tree.install_state(title_id)
+        );
 
-    for entry in bytes.chunks(12) {
-        entries.push(Entry::from(
-            <&[u8] as TryInto<&[u8; 12]>>::try_into(entry).unwrap(),
-        ))
+        if let Some(name) = title_db.and_then(|db| db.name_for_title_id(title_id)) {
+            line.push_str(&format!(" - {name}"));
+        }
+
+        if to_stdout {
+            println!("{line}");
+        } else {
+            eprintln!("{line}");
+        }
     }
 
-    Some(entries)
+    ExitCode::SUCCESS
 }
 
-impl From<&[u8; 12]> for Entry {
-    fn from(value: &[u8; 12]) -> Self {
-        Self {
-            title_id: u64::from_be_bytes(value[0..8].try_into().unwrap()),
-            // padding: u16::from_be_bytes(value[8..10].try_into().unwrap()),
-            uid: u16::from_be_bytes(value[10..12].try_into().unwrap()),
+fn get_entries_from_file(file_name: &str, keys: Option<&str>, recover: bool) -> Option<Vec<Entry>> {
+    let result = read_uid_sys(file_name, keys).and_then(|bytes| {
+        if recover {
+            let recovered = parse_entries_lossy(&bytes);
+
+            if let Some(t) = recovered.truncation {
+                eprintln!(
+                    "\"{file_name}\": Ignoring {} trailing bytes at offset {:#X}",
+                    t.len, t.offset
+                );
+            }
+
+            Ok(recovered.entries)
+        } else {
+            parse_entries(&bytes)
+        }
+    });
+
+    match result {
+        Ok(v) => Some(v),
+        Err(e) => {
+            report_read_error(file_name, e);
+            None
         }
     }
 }
 
-struct Entry {
-    title_id: u64,
-    // padding: u16,
-    uid: u16,
+/// Reads the contents of a uid.sys file, or of the uid.sys inside a NAND dump
+/// if the file has the size of one.
+fn read_uid_sys(file_name: &str, keys: Option<&str>) -> Result<Vec<u8>, Error> {
+    let len = fs::metadata(file_name)?.len();
+
+    if Layout::detect(len).is_none() {
+        return Ok(fs::read(file_name)?);
+    }
+
+    let keys = keys.map(Keys::open).transpose()?;
+    read_uid_sys_from_nand(file_name, keys)
+}
+
+fn report_read_error(file_name: &str, error: Error) {
+    match error {
+        Error::IoError(e) => match e.kind() {
+            std::io::ErrorKind::NotFound => eprintln!("\"{file_name}\": File not found"),
+            _ => eprintln!("\"{file_name}\": Error opening file"),
+        },
+        e => eprintln!("\"{file_name}\": {e}"),
+    }
 }
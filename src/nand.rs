@@ -0,0 +1,489 @@
+//! Access to raw BootMii NAND dumps (nand.bin) and the keys protecting them.
+
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use aes::cipher::block_padding::NoPadding;
+use aes::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+
+use crate::Error;
+
+/// Size of the data area of a NAND page.
+pub const PAGE_SIZE: usize = 0x800;
+/// Size of the spare area following each page in dumps with ECC data.
+pub const SPARE_SIZE: usize = 0x40;
+pub const PAGES_PER_CLUSTER: usize = 8;
+/// Size of a cluster, the allocation unit of the filesystem.
+pub const CLUSTER_SIZE: usize = PAGE_SIZE * PAGES_PER_CLUSTER;
+pub const CLUSTER_COUNT: usize = 0x8000;
+/// Number of clusters in an erase block, the unit bad blocks are marked in.
+pub const CLUSTERS_PER_BLOCK: usize = 8;
+/// Size of a keys.bin file, also appended to newer BootMii dumps.
+pub const KEYS_SIZE: usize = 0x400;
+
+/// Size of the blocks ECC is computed over; each page has four.
+pub const ECC_BLOCK_SIZE: usize = 0x200;
+
+const PAGE_COUNT: usize = CLUSTER_COUNT * PAGES_PER_CLUSTER;
+/// Offset of the ECC data in the spare area.
+const SPARE_ECC_OFFSET: usize = 0x30;
+
+type Aes128CbcDec = cbc::Decryptor<aes::Aes128>;
+type Aes128CbcEnc = cbc::Encryptor<aes::Aes128>;
+
+/// The console-specific keys protecting the NAND filesystem.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Keys {
+    /// AES-128 key used to encrypt file data.
+    pub aes: [u8; 16],
+    /// HMAC-SHA1 key used to authenticate file data and superblocks.
+    pub hmac: [u8; 20],
+}
+
+impl Keys {
+    /// Extracts the NAND keys from the contents of a BootMii keys.bin.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < KEYS_SIZE {
+            return Err(Error::ReadError);
+        }
+
+        Ok(Self {
+            hmac: bytes[0x144..0x158].try_into().unwrap(),
+            aes: bytes[0x158..0x168].try_into().unwrap(),
+        })
+    }
+
+    /// Reads the NAND keys from a BootMii keys.bin file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+/// How pages are laid out in a NAND dump.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Each page is followed by its 64-byte spare area (ECC and HMAC data).
+    WithEcc,
+    /// Only page data was dumped.
+    WithoutEcc,
+}
+
+impl Layout {
+    /// Detects the layout of a dump from its size, and whether a keys.bin
+    /// block is appended to it.
+    pub fn detect(len: u64) -> Option<(Self, bool)> {
+        let with_ecc = (PAGE_COUNT * (PAGE_SIZE + SPARE_SIZE)) as u64;
+        let without_ecc = (PAGE_COUNT * PAGE_SIZE) as u64;
+        let keys = KEYS_SIZE as u64;
+
+        match len {
+            l if l == with_ecc => Some((Self::WithEcc, false)),
+            l if l == with_ecc + keys => Some((Self::WithEcc, true)),
+            l if l == without_ecc => Some((Self::WithoutEcc, false)),
+            l if l == without_ecc + keys => Some((Self::WithoutEcc, true)),
+            _ => None,
+        }
+    }
+
+    /// Size of a page, including its spare area if present.
+    pub fn page_stride(&self) -> usize {
+        match self {
+            Self::WithEcc => PAGE_SIZE + SPARE_SIZE,
+            Self::WithoutEcc => PAGE_SIZE,
+        }
+    }
+}
+
+/// A raw BootMii NAND dump.
+pub struct NandImage<R> {
+    reader: R,
+    layout: Layout,
+    keys: Keys,
+}
+
+impl NandImage<File> {
+    /// Opens a nand.bin. `keys` must be given unless the dump has a keys.bin
+    /// block appended, in which case they are read from it.
+    pub fn open(path: impl AsRef<Path>, keys: Option<Keys>) -> Result<Self, Error> {
+        Self::new(File::open(path)?, keys)
+    }
+}
+
+impl<R: Read + Seek> NandImage<R> {
+    pub fn new(mut reader: R, keys: Option<Keys>) -> Result<Self, Error> {
+        let len = reader.seek(SeekFrom::End(0))?;
+        let (layout, has_keys) = Layout::detect(len).ok_or(Error::NotNand)?;
+
+        let keys = match keys {
+            Some(k) => k,
+            None if has_keys => {
+                let mut bytes = vec![0; KEYS_SIZE];
+                reader.seek(SeekFrom::Start(len - KEYS_SIZE as u64))?;
+                reader.read_exact(&mut bytes)?;
+                Keys::from_bytes(&bytes)?
+            }
+            None => return Err(Error::MissingKeys),
+        };
+
+        Ok(Self {
+            reader,
+            layout,
+            keys,
+        })
+    }
+
+    pub fn layout(&self) -> Layout {
+        self.layout
+    }
+
+    pub fn keys(&self) -> &Keys {
+        &self.keys
+    }
+
+    /// Reads the data area of a page.
+    pub fn read_page(&mut self, page: usize) -> Result<Vec<u8>, Error> {
+        let mut data = vec![0; PAGE_SIZE];
+        self.seek_page(page)?;
+        self.reader.read_exact(&mut data)?;
+        Ok(data)
+    }
+
+    /// Reads the data of a cluster as stored, without decrypting it.
+    pub fn read_cluster_raw(&mut self, cluster: u16) -> Result<Vec<u8>, Error> {
+        Ok(self.read_cluster_with_spare(cluster)?.0)
+    }
+
+    /// Reads the data of a cluster as stored, together with the spare area
+    /// of each of its pages. There are no spare areas in dumps without ECC
+    /// data.
+    pub fn read_cluster_with_spare(
+        &mut self,
+        cluster: u16,
+    ) -> Result<(Vec<u8>, Vec<[u8; SPARE_SIZE]>), Error> {
+        let stride = self.layout.page_stride();
+        let mut raw = vec![0; stride * PAGES_PER_CLUSTER];
+
+        self.seek_page(cluster as usize * PAGES_PER_CLUSTER)?;
+        self.reader.read_exact(&mut raw)?;
+
+        let mut data = Vec::with_capacity(CLUSTER_SIZE);
+        let mut spares = vec![];
+
+        for page in raw.chunks_exact(stride) {
+            data.extend(&page[..PAGE_SIZE]);
+
+            if self.layout == Layout::WithEcc {
+                spares.push(page[PAGE_SIZE..].try_into().unwrap());
+            }
+        }
+
+        Ok((data, spares))
+    }
+
+    /// Reads and decrypts the data of a cluster.
+    ///
+    /// In dumps with ECC data, single flipped bits are corrected before
+    /// decrypting, and damage beyond that fails with
+    /// [`Error::UncorrectableEcc`].
+    pub fn read_cluster(&mut self, cluster: u16) -> Result<Vec<u8>, Error> {
+        Ok(self.read_cluster_with_hmac(cluster)?.0)
+    }
+
+    /// Reads, corrects and decrypts the data of a cluster like
+    /// [`NandImage::read_cluster`], together with the two copies of the HMAC
+    /// stored for it. There is no HMAC in dumps without ECC data.
+    pub fn read_cluster_with_hmac(
+        &mut self,
+        cluster: u16,
+    ) -> Result<(Vec<u8>, Option<StoredHmac>), Error> {
+        let (mut data, spares) = self.read_cluster_with_spare(cluster)?;
+
+        for (page, spare) in data.chunks_exact_mut(PAGE_SIZE).zip(&spares) {
+            let ecc = spare_ecc(spare);
+
+            if page.iter().chain(ecc).all(|&b| b == 0xFF) {
+                continue;
+            }
+
+            for (block, stored) in page.chunks_exact_mut(ECC_BLOCK_SIZE).zip(ecc.chunks(4)) {
+                if correct_ecc(block, stored) == EccCheck::Uncorrectable {
+                    return Err(Error::UncorrectableEcc { cluster });
+                }
+            }
+        }
+
+        decrypt_cluster(&self.keys, &mut data);
+        let hmac = (self.layout == Layout::WithEcc).then(|| spare_hmac(&spares[6], &spares[7]));
+
+        Ok((data, hmac))
+    }
+
+    fn seek_page(&mut self, page: usize) -> Result<(), Error> {
+        if page >= PAGE_COUNT {
+            return Err(Error::BadFilesystem);
+        }
+
+        let offset = (page * self.layout.page_stride()) as u64;
+        self.reader.seek(SeekFrom::Start(offset))?;
+        Ok(())
+    }
+}
+
+impl<R: Read + Write + Seek> NandImage<R> {
+    /// Writes the data of a cluster as is, without encrypting it.
+    ///
+    /// In dumps with ECC data, the spare area of each page is rewritten with
+    /// freshly computed ECC and, if given, the cluster's HMAC.
+    pub fn write_cluster_raw(
+        &mut self,
+        cluster: u16,
+        data: &[u8],
+        hmac: Option<&[u8; 20]>,
+    ) -> Result<(), Error> {
+        assert_eq!(data.len(), CLUSTER_SIZE);
+        let first_page = cluster as usize * PAGES_PER_CLUSTER;
+
+        for (i, page) in data.chunks_exact(PAGE_SIZE).enumerate() {
+            self.seek_page(first_page + i)?;
+            self.reader.write_all(page)?;
+
+            if self.layout == Layout::WithEcc {
+                self.reader.write_all(&make_spare(page, i, hmac))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Encrypts and writes the data of a cluster.
+    pub fn write_cluster(
+        &mut self,
+        cluster: u16,
+        data: &[u8],
+        hmac: Option<&[u8; 20]>,
+    ) -> Result<(), Error> {
+        let mut data = data.to_vec();
+        encrypt_cluster(&self.keys, &mut data);
+        self.write_cluster_raw(cluster, &data, hmac)
+    }
+}
+
+/// Builds the spare area of a page: the good block marker, the cluster's
+/// HMAC for the last two pages of a cluster, and the ECC of the page.
+///
+/// The HMAC is stored twice: the first copy and 12 bytes of the second in
+/// page 6, the remaining 8 bytes in page 7.
+fn make_spare(page: &[u8], index_in_cluster: usize, hmac: Option<&[u8; 20]>) -> [u8; SPARE_SIZE] {
+    let mut spare = [0; SPARE_SIZE];
+    spare[0] = 0xFF;
+
+    if let Some(hmac) = hmac {
+        match index_in_cluster {
+            6 => {
+                spare[1..21].copy_from_slice(hmac);
+                spare[21..33].copy_from_slice(&hmac[..12]);
+            }
+            7 => spare[1..9].copy_from_slice(&hmac[12..]),
+            _ => {}
+        }
+    }
+
+    spare[SPARE_ECC_OFFSET..].copy_from_slice(&page_ecc(page));
+    spare
+}
+
+/// The two copies of a cluster's HMAC kept in its spare areas.
+pub type StoredHmac = ([u8; 20], [u8; 20]);
+
+/// Extracts the HMAC of a cluster from the spare areas of its last two
+/// pages, as (first copy, second copy).
+pub fn spare_hmac(page6: &[u8], page7: &[u8]) -> StoredHmac {
+    let first = page6[1..21].try_into().unwrap();
+    let mut second = [0; 20];
+    second[..12].copy_from_slice(&page6[21..33]);
+    second[12..].copy_from_slice(&page7[1..9]);
+    (first, second)
+}
+
+/// The ECC data of a page as stored in its spare area: four bytes for each
+/// 512-byte block.
+pub fn page_ecc(page: &[u8]) -> [u8; 16] {
+    let mut ecc = [0; 16];
+
+    for (i, block) in page.chunks_exact(ECC_BLOCK_SIZE).enumerate() {
+        ecc[i * 4..i * 4 + 4].copy_from_slice(&block_ecc(block));
+    }
+
+    ecc
+}
+
+/// The ECC data stored in the spare area of a page.
+pub fn spare_ecc(spare: &[u8]) -> &[u8] {
+    &spare[SPARE_ECC_OFFSET..]
+}
+
+/// Compares the ECC of a 512-byte block as stored and as computed.
+pub fn check_ecc(stored: &[u8], computed: &[u8]) -> EccCheck {
+    let word = |ecc: &[u8], i: usize| u16::from_le_bytes([ecc[i], ecc[i + 1]]) & 0xFFF;
+
+    let syndrome0 = word(stored, 0) ^ word(computed, 0);
+    let syndrome1 = word(stored, 2) ^ word(computed, 2);
+
+    if syndrome0 == 0 && syndrome1 == 0 {
+        EccCheck::Ok
+    } else if syndrome0 ^ syndrome1 == 0xFFF {
+        // One flipped data bit flips every parity bit in exactly one of
+        // each pair.
+        EccCheck::Correctable
+    } else if syndrome0.count_ones() + syndrome1.count_ones() == 1 {
+        // The stored ECC itself has one flipped bit.
+        EccCheck::Correctable
+    } else {
+        EccCheck::Uncorrectable
+    }
+}
+
+/// Checks a 512-byte block against its stored ECC, fixing a single flipped
+/// data bit in place.
+pub fn correct_ecc(block: &mut [u8], stored: &[u8]) -> EccCheck {
+    let computed = block_ecc(block);
+    let word = |ecc: &[u8], i: usize| u16::from_le_bytes([ecc[i], ecc[i + 1]]) & 0xFFF;
+
+    let syndrome0 = word(stored, 0) ^ word(&computed, 0);
+    let syndrome1 = word(stored, 2) ^ word(&computed, 2);
+
+    if syndrome0 ^ syndrome1 == 0xFFF {
+        // The parities that changed in the second half spell out the
+        // position of the flipped bit: byte in the high bits, bit in the low.
+        block[(syndrome1 >> 3) as usize] ^= 1 << (syndrome1 & 7);
+    }
+
+    check_ecc(stored, &computed)
+}
+
+/// The outcome of an ECC check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EccCheck {
+    Ok,
+    /// A single bit is wrong and can be corrected.
+    Correctable,
+    /// The data is damaged beyond what ECC can repair.
+    Uncorrectable,
+}
+
+/// Computes the Hamming code of a 512-byte block, as done by the NAND
+/// controller.
+fn block_ecc(data: &[u8]) -> [u8; 4] {
+    let mut a = [[0u8; 2]; 12];
+
+    for (i, &x) in data.iter().enumerate() {
+        for j in 0..9 {
+            a[3 + j][(i >> j) & 1] ^= x;
+        }
+    }
+
+    let x = a[3][0] ^ a[3][1];
+    a[0] = [x & 0x55, x & 0xAA];
+    a[1] = [x & 0x33, x & 0xCC];
+    a[2] = [x & 0x0F, x & 0xF0];
+
+    let mut a0: u32 = 0;
+    let mut a1: u32 = 0;
+
+    for (j, pair) in a.iter().enumerate() {
+        a0 |= (pair[0].count_ones() & 1) << j;
+        a1 |= (pair[1].count_ones() & 1) << j;
+    }
+
+    [a0 as u8, (a0 >> 8) as u8, a1 as u8, (a1 >> 8) as u8]
+}
+
+/// Encrypts a cluster in place, see [`decrypt_cluster`].
+pub fn encrypt_cluster(keys: &Keys, data: &mut [u8]) {
+    let len = data.len();
+    Aes128CbcEnc::new(&keys.aes.into(), &[0; 16].into())
+        .encrypt_padded_mut::<NoPadding>(data, len)
+        .expect("cluster size is a multiple of the AES block size");
+}
+
+/// Decrypts a cluster in place. Every cluster is encrypted on its own with
+/// AES-128-CBC and a zero IV.
+pub fn decrypt_cluster(keys: &Keys, data: &mut [u8]) {
+    Aes128CbcDec::new(&keys.aes.into(), &[0; 16].into())
+        .decrypt_padded_mut::<NoPadding>(data)
+        .expect("cluster size is a multiple of the AES block size");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page() -> Vec<u8> {
+        (0..PAGE_SIZE).map(|i| (i * 7 + i / 256) as u8).collect()
+    }
+
+    #[test]
+    fn ecc_of_intact_page_matches() {
+        let page = page();
+        let stored = page_ecc(&page);
+
+        for (i, block) in page.chunks_exact(ECC_BLOCK_SIZE).enumerate() {
+            assert_eq!(
+                check_ecc(&stored[i * 4..i * 4 + 4], &block_ecc(block)),
+                EccCheck::Ok
+            );
+        }
+    }
+
+    #[test]
+    fn single_bit_error_is_corrected() {
+        let original = page();
+        let stored = page_ecc(&original);
+
+        for (byte, bit) in [(0, 0), (0x123, 5), (ECC_BLOCK_SIZE - 1, 7)] {
+            let mut page = original.clone();
+            page[ECC_BLOCK_SIZE + byte] ^= 1 << bit;
+
+            let block = &mut page[ECC_BLOCK_SIZE..2 * ECC_BLOCK_SIZE];
+            assert_eq!(correct_ecc(block, &stored[4..8]), EccCheck::Correctable);
+            assert_eq!(page, original);
+        }
+    }
+
+    #[test]
+    fn single_bit_error_in_stored_ecc_is_correctable() {
+        let mut page = page();
+        let mut stored = page_ecc(&page);
+        stored[1] ^= 0x02;
+
+        let block = &mut page[..ECC_BLOCK_SIZE];
+        assert_eq!(correct_ecc(block, &stored[..4]), EccCheck::Correctable);
+        assert_eq!(page, self::page());
+    }
+
+    #[test]
+    fn two_bit_error_is_uncorrectable() {
+        let original = page();
+        let stored = page_ecc(&original);
+
+        let mut page = original.clone();
+        page[0x10] ^= 0x01;
+        page[0x1F0] ^= 0x40;
+
+        let block = &mut page[..ECC_BLOCK_SIZE];
+        assert_eq!(correct_ecc(block, &stored[..4]), EccCheck::Uncorrectable);
+    }
+
+    #[test]
+    fn hmac_round_trips_through_spare_areas() {
+        let hmac: [u8; 20] = std::array::from_fn(|i| i as u8 + 1);
+        let page = page();
+
+        let page6 = make_spare(&page, 6, Some(&hmac));
+        let page7 = make_spare(&page, 7, Some(&hmac));
+
+        assert_eq!(spare_hmac(&page6, &page7), (hmac, hmac));
+        assert_eq!(spare_ecc(&page6), page_ecc(&page));
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+use crate::nand::{
+    correct_ecc, decrypt_cluster, spare_ecc, spare_hmac, EccCheck, Layout, CLUSTERS_PER_BLOCK,
+    CLUSTER_COUNT, ECC_BLOCK_SIZE, PAGES_PER_CLUSTER, PAGE_SIZE,
+};
+use crate::sffs::{
+    cluster_hmac, superblock_hmac, superblock_slots, Sffs, FAT_BAD, SUPERBLOCK_CLUSTERS,
+    SUPERBLOCK_SIZE,
+};
+use crate::Error;
+
+/// An ECC mismatch in a 512-byte block of a page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EccError {
+    pub page: usize,
+    /// Which of the four 512-byte blocks of the page is affected.
+    pub subpage: usize,
+    pub correctable: bool,
+}
+
+impl EccError {
+    pub fn cluster(&self) -> u16 {
+        (self.page / PAGES_PER_CLUSTER) as u16
+    }
+}
+
+/// Why an erase block is considered bad.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BadBlockReason {
+    /// The bad block marker in the spare area of its first page is set.
+    Marker,
+    /// The filesystem marks its clusters as bad in the FAT.
+    Fat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BadBlock {
+    pub block: usize,
+    pub reason: BadBlockReason,
+}
+
+/// The result of checking a superblock slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SuperblockCheck {
+    pub cluster: u16,
+    pub generation: u32,
+    pub hmac_ok: bool,
+    /// Whether this is the superblock the filesystem was mounted from.
+    pub in_use: bool,
+}
+
+/// The damage found in a file's clusters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileCheck {
+    pub path: String,
+    pub index: usize,
+    /// Clusters whose HMAC does not match their contents.
+    pub bad_hmac: Vec<u16>,
+    /// Clusters with uncorrectable ECC errors.
+    pub bad_ecc: Vec<u16>,
+    /// Clusters lying in bad blocks.
+    pub bad_blocks: Vec<u16>,
+    /// Whether the file's FAT chain is broken or loops, in which case its
+    /// clusters are not checked.
+    pub bad_chain: bool,
+}
+
+impl FileCheck {
+    pub fn is_ok(&self) -> bool {
+        !self.bad_chain
+            && self.bad_hmac.is_empty()
+            && self.bad_ecc.is_empty()
+            && self.bad_blocks.is_empty()
+    }
+}
+
+/// The result of checking the integrity of a NAND dump.
+#[derive(Debug, Clone, Default)]
+pub struct NandReport {
+    pub pages_checked: usize,
+    /// Pages skipped because they are erased.
+    pub pages_erased: usize,
+    pub ecc_errors: Vec<EccError>,
+    pub bad_blocks: Vec<BadBlock>,
+    pub superblocks: Vec<SuperblockCheck>,
+    /// Every file of the filesystem, damaged or not, in path order.
+    pub files: Vec<FileCheck>,
+}
+
+impl NandReport {
+    /// Whether no problem that could affect file contents was found.
+    /// Correctable ECC errors do not count.
+    pub fn is_ok(&self) -> bool {
+        self.ecc_errors.iter().all(|e| e.correctable)
+            && self.bad_blocks.is_empty()
+            && self
+                .superblocks
+                .iter()
+                .filter(|s| s.in_use)
+                .all(|s| s.hmac_ok)
+            && self.files.iter().all(FileCheck::is_ok)
+    }
+
+    pub fn file(&self, path: &str) -> Option<&FileCheck> {
+        self.files.iter().find(|f| f.path == path)
+    }
+
+    pub fn damaged_files(&self) -> impl Iterator<Item = &FileCheck> {
+        self.files.iter().filter(|f| !f.is_ok())
+    }
+}
+
+/// Checks the integrity of a NAND dump: the ECC of every page, bad block
+/// markers, the HMAC of every superblock and of every cluster of every file.
+/// Single-bit errors are corrected before HMACs are checked, and files with
+/// a broken FAT chain are reported rather than stopping the check.
+///
+/// Fails with [`Error::NoSpareData`] for dumps without ECC data, which also
+/// lack the stored HMACs.
+pub fn check_nand<R: Read + Seek>(sffs: &mut Sffs<R>) -> Result<NandReport, Error> {
+    if sffs.nand().layout() != Layout::WithEcc {
+        return Err(Error::NoSpareData);
+    }
+
+    let mut report = NandReport::default();
+    let superblock = sffs.superblock().clone();
+    let keys = *sffs.nand().keys();
+
+    // Which file cluster, as (FST index, position in chain), each cluster holds.
+    let mut owners = HashMap::<u16, (usize, usize)>::new();
+
+    for (path, index) in superblock.walk(0)? {
+        if !superblock.fst[index].is_file() {
+            continue;
+        }
+
+        let chain = sffs.file_clusters(index);
+
+        for (i, &cluster) in chain.iter().flatten().enumerate() {
+            owners.insert(cluster, (index, i));
+        }
+
+        report.files.push(FileCheck {
+            path,
+            index,
+            bad_hmac: vec![],
+            bad_ecc: vec![],
+            bad_blocks: vec![],
+            bad_chain: chain.is_err(),
+        });
+    }
+
+    report.files.sort_by(|a, b| a.path.cmp(&b.path));
+    let file_position: HashMap<usize, usize> = report
+        .files
+        .iter()
+        .enumerate()
+        .map(|(i, f)| (f.index, i))
+        .collect();
+
+    let slots: HashMap<u16, u32> = superblock_slots(sffs.nand())?
+        .into_iter()
+        .map(|(generation, cluster)| (cluster, generation))
+        .collect();
+    let mut superblock_data = vec![];
+
+    for block in 0..CLUSTER_COUNT / CLUSTERS_PER_BLOCK {
+        let first = block * CLUSTERS_PER_BLOCK;
+        let fat_bad = (first..first + CLUSTERS_PER_BLOCK).any(|c| superblock.fat[c] == FAT_BAD);
+        let mut marker_bad = false;
+
+        for cluster in first..first + CLUSTERS_PER_BLOCK {
+            let cluster = cluster as u16;
+            let (mut data, spares) = sffs.nand().read_cluster_with_spare(cluster)?;
+
+            if cluster as usize == first && spares[0][0] != 0xFF {
+                marker_bad = true;
+            }
+
+            let mut ecc_ok = true;
+
+            for (i, (page, spare)) in data.chunks_exact_mut(PAGE_SIZE).zip(&spares).enumerate() {
+                let page_index = cluster as usize * PAGES_PER_CLUSTER + i;
+
+                if page.iter().all(|&b| b == 0xFF) && spare_ecc(spare).iter().all(|&b| b == 0xFF) {
+                    report.pages_erased += 1;
+                    continue;
+                }
+
+                report.pages_checked += 1;
+
+                for (subpage, block) in page.chunks_exact_mut(ECC_BLOCK_SIZE).enumerate() {
+                    match correct_ecc(block, &spare_ecc(spare)[subpage * 4..subpage * 4 + 4]) {
+                        EccCheck::Ok => {}
+                        status => {
+                            let correctable = status == EccCheck::Correctable;
+                            ecc_ok &= correctable;
+                            report.ecc_errors.push(EccError {
+                                page: page_index,
+                                subpage,
+                                correctable,
+                            });
+                        }
+                    }
+                }
+            }
+
+            let stored_hmac = spare_hmac(&spares[6], &spares[7]);
+
+            if let Some(&(index, chain_index)) = owners.get(&cluster) {
+                let file = &mut report.files[file_position[&index]];
+
+                if !ecc_ok {
+                    file.bad_ecc.push(cluster);
+                }
+
+                decrypt_cluster(&keys, &mut data);
+                let hmac = cluster_hmac(&keys, &superblock.fst[index], index, chain_index, &data);
+
+                if hmac != stored_hmac.0 && hmac != stored_hmac.1 {
+                    file.bad_hmac.push(cluster);
+                }
+            } else if let Some(start) = slots
+                .keys()
+                .copied()
+                .find(|&s| (s..s + SUPERBLOCK_CLUSTERS).contains(&cluster))
+            {
+                superblock_data.extend(data);
+
+                if cluster == start + SUPERBLOCK_CLUSTERS - 1 {
+                    let hmac = superblock_hmac(&keys, start, &superblock_data[..SUPERBLOCK_SIZE]);
+
+                    report.superblocks.push(SuperblockCheck {
+                        cluster: start,
+                        generation: slots[&start],
+                        hmac_ok: hmac == stored_hmac.0 || hmac == stored_hmac.1,
+                        in_use: start == superblock.cluster,
+                    });
+
+                    superblock_data.clear();
+                }
+            }
+        }
+
+        if marker_bad || fat_bad {
+            report.bad_blocks.push(BadBlock {
+                block,
+                reason: if marker_bad {
+                    BadBlockReason::Marker
+                } else {
+                    BadBlockReason::Fat
+                },
+            });
+
+            for cluster in first..first + CLUSTERS_PER_BLOCK {
+                if let Some(&(index, _)) = owners.get(&(cluster as u16)) {
+                    report.files[file_position[&index]]
+                        .bad_blocks
+                        .push(cluster as u16);
+                }
+            }
+        }
+    }
+
+    Ok(report)
+}
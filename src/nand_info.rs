@@ -0,0 +1,175 @@
+use std::collections::HashMap;
+use std::io::{Read, Seek};
+
+use crate::{
+    classify_ios, content_path, listing, ticket_path, tmd_path, CertChain, Entry, Error, IosKind,
+    ListingRow, NandSource, NandTree, Signature, SignatureStatus, Ticket, TitleDb, Tmd,
+    CERT_SYS_PATH,
+};
+
+/// What is known about the titles of a listing from a NAND dump or an
+/// extracted NAND.
+#[derive(Debug, Clone, Default)]
+pub struct NandInfo {
+    pub tree: Option<NandTree>,
+    pub tmds: HashMap<u64, Tmd>,
+    pub tickets: HashMap<u64, Ticket>,
+    pub ios_kinds: HashMap<u64, IosKind>,
+    /// The certificates of cert.sys, unless it was not read.
+    pub certs: Option<CertChain>,
+}
+
+impl NandInfo {
+    /// Reads the TMDs of the listed titles and classifies the installed IOS.
+    /// Missing or unreadable TMDs are skipped.
+    pub fn load<R: Read + Seek>(source: &mut NandSource<R>, entries: &[Entry]) -> Self {
+        let tree = source.tree().cloned();
+        let mut read = |path: &str| source.read_file(path).ok();
+
+        let mut tmds = HashMap::new();
+        let mut ios_kinds = HashMap::new();
+
+        for entry in entries {
+            let Some(tmd) = read(&tmd_path(entry.title_id)).and_then(|b| Tmd::from_bytes(&b).ok())
+            else {
+                continue;
+            };
+
+            if let Some(number) = entry.ios_number() {
+                let first_content = tmd
+                    .contents
+                    .iter()
+                    .min_by_key(|c| c.index)
+                    .and_then(|c| content_path(entry.title_id, c, &[]))
+                    .and_then(|path| read(&path));
+
+                ios_kinds.insert(
+                    entry.title_id,
+                    classify_ios(number, &tmd, first_content.as_deref()),
+                );
+            }
+
+            tmds.insert(entry.title_id, tmd);
+        }
+
+        Self {
+            tree,
+            tmds,
+            ios_kinds,
+            ..Default::default()
+        }
+    }
+
+    /// Reads the tickets of the listed titles and cert.sys, to check the
+    /// signatures of TMDs and tickets. Missing or unreadable tickets are
+    /// skipped; a missing or unreadable cert.sys is returned as an error,
+    /// leaving only fakesigns detectable.
+    pub fn load_signatures<R: Read + Seek>(
+        &mut self,
+        source: &mut NandSource<R>,
+        entries: &[Entry],
+    ) -> Result<(), Error> {
+        for entry in entries {
+            if let Some(ticket) = source
+                .read_file(&ticket_path(entry.title_id))
+                .ok()
+                .and_then(|b| Ticket::from_bytes(&b).ok())
+            {
+                self.tickets.insert(entry.title_id, ticket);
+            }
+        }
+
+        let certs = CertChain::from_bytes(&source.read_file(CERT_SYS_PATH)?)?;
+        self.certs = Some(certs);
+        Ok(())
+    }
+
+    /// The signature status of a title's TMD and ticket, if read. Without
+    /// cert.sys, only fakesigns are reported.
+    pub fn signatures(&self, title_id: u64) -> (Option<SignatureStatus>, Option<SignatureStatus>) {
+        let status = |signature: &Signature| match &self.certs {
+            Some(certs) => Some(certs.verify(signature)),
+            None if signature.is_fakesigned() => Some(SignatureStatus::Fakesigned),
+            None => None,
+        };
+
+        (
+            self.tmds.get(&title_id).and_then(|t| status(&t.signature)),
+            self.tickets
+                .get(&title_id)
+                .and_then(|t| status(&t.signature)),
+        )
+    }
+
+    /// The listing rows of the entries, with everything known about them.
+    pub fn rows(&self, entries: &[Entry], title_db: Option<&TitleDb>) -> Vec<ListingRow> {
+        listing(entries, title_db, self.tree.as_ref())
+            .into_iter()
+            .zip(entries)
+            .map(|(row, entry)| {
+                let row = match self.tmds.get(&entry.title_id) {
+                    Some(tmd) => row.with_tmd(tmd),
+                    None => row,
+                };
+
+                let row = match self.ios_kinds.get(&entry.title_id) {
+                    Some(kind) => row.with_ios_kind(kind),
+                    None => row,
+                };
+
+                let (tmd, ticket) = self.signatures(entry.title_id);
+
+                ListingRow {
+                    tmd_signature: tmd.map(|s| s.name()),
+                    ticket_signature: ticket.map(|s| s.name()),
+                    ..row
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tmd(title_id: u64, signature: Vec<u8>, hash: [u8; 20]) -> Tmd {
+        Tmd {
+            signature: Signature {
+                signature_type: 0x10001,
+                signature,
+                issuer: "Root-CA00000001-CP00000004".to_owned(),
+                hash,
+            },
+            sys_version: 0,
+            title_id,
+            title_type: 1,
+            group_id: 0,
+            region: 0,
+            access_rights: 0,
+            title_version: 0,
+            boot_index: 0,
+            contents: vec![],
+        }
+    }
+
+    #[test]
+    fn fakesigns_are_reported_without_cert_sys() {
+        let mut nand = NandInfo::default();
+        nand.tmds.insert(1, tmd(1, vec![0; 0x100], [0; 20]));
+        nand.tmds.insert(2, tmd(2, vec![0xAB; 0x100], [0; 20]));
+
+        assert_eq!(
+            nand.signatures(1),
+            (Some(SignatureStatus::Fakesigned), None)
+        );
+        assert_eq!(nand.signatures(2), (None, None));
+        assert_eq!(nand.signatures(3), (None, None));
+
+        nand.certs = Some(CertChain::default());
+        assert_eq!(
+            nand.signatures(2),
+            (Some(SignatureStatus::UnknownIssuer), None)
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use std::collections::HashMap;
+
+use crate::sffs::Superblock;
+use crate::{Entry, Error, Uid};
+
+/// The files and directories a uid.sys entry owns on the NAND.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedFiles {
+    pub entry: Entry,
+    /// Absolute paths, in filesystem order.
+    pub paths: Vec<String>,
+}
+
+/// A file or directory owned by a UID missing from uid.sys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnknownOwner {
+    pub uid: u32,
+    pub path: String,
+}
+
+/// How the UIDs of uid.sys match the owners recorded in the filesystem.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Ownership {
+    /// One item per uid.sys entry, in uid.sys order.
+    pub titles: Vec<OwnedFiles>,
+    /// Files and directories whose owner is neither root nor in uid.sys.
+    pub unknown: Vec<UnknownOwner>,
+}
+
+impl Ownership {
+    /// Entries of uid.sys that own no file or directory.
+    pub fn unused(&self) -> impl Iterator<Item = &Entry> {
+        self.titles
+            .iter()
+            .filter(|t| t.paths.is_empty())
+            .map(|t| &t.entry)
+    }
+}
+
+/// Maps each uid.sys entry to the files and directories it owns.
+///
+/// Files owned by the root UID are system files and are never reported as
+/// unknown. When a UID appears more than once in uid.sys, its files are
+/// listed under its first entry.
+pub fn ownership(entries: &[Entry], superblock: &Superblock) -> Result<Ownership, Error> {
+    let mut titles: Vec<_> = entries
+        .iter()
+        .map(|&entry| OwnedFiles {
+            entry,
+            paths: vec![],
+        })
+        .collect();
+
+    let mut positions = HashMap::new();
+
+    for (i, entry) in entries.iter().enumerate() {
+        positions.entry(u32::from(entry.uid.0)).or_insert(i);
+    }
+
+    let mut unknown = vec![];
+
+    for (path, index) in superblock.walk(0)? {
+        let uid = superblock.fst[index].uid;
+
+        if let Some(&i) = positions.get(&uid) {
+            titles[i].paths.push(path);
+        } else if uid != u32::from(Uid::ROOT.0) {
+            unknown.push(UnknownOwner { uid, path });
+        }
+    }
+
+    Ok(Ownership { titles, unknown })
+}
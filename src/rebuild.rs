@@ -0,0 +1,131 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::Path;
+
+use crate::sffs::Superblock;
+use crate::tree::hex_name;
+use crate::{Entry, Error, NandTree, Uid, SYSTEM_MENU_TITLE_ID};
+
+/// A uid.sys reconstructed from the titles installed on a NAND.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Rebuilt {
+    /// The entries, ordered by UID.
+    pub entries: Vec<Entry>,
+    /// Titles whose UID could not be told from file ownership and were given
+    /// a new one after every known UID.
+    pub assigned: Vec<u64>,
+    /// Titles whose files are owned by more than one UID. The UID owning the
+    /// most files was picked.
+    pub ambiguous: Vec<u64>,
+}
+
+/// Reconstructs uid.sys from the /title tree of a NAND filesystem.
+///
+/// Each title is given the UID that owns the most files and directories
+/// below `/title/<upper>/<lower>`, ignoring those owned by root.
+pub fn rebuild_from_sffs(superblock: &Superblock) -> Result<Rebuilt, Error> {
+    let mut owners = BTreeMap::<u64, HashMap<u16, usize>>::new();
+
+    for (path, index) in superblock.walk(0)? {
+        let Some(title_id) = title_id_of(&path) else {
+            continue;
+        };
+
+        let counts = owners.entry(title_id).or_default();
+
+        if let Ok(uid) = u16::try_from(superblock.fst[index].uid) {
+            if uid != Uid::ROOT.0 {
+                *counts.entry(uid).or_default() += 1;
+            }
+        }
+    }
+
+    let mut rebuilt = Rebuilt::default();
+    let mut unowned = vec![];
+
+    for (title_id, counts) in owners {
+        let owner = counts
+            .iter()
+            .max_by(|(a, m), (b, n)| m.cmp(n).then(b.cmp(a)))
+            .map(|(&uid, _)| uid);
+
+        match owner {
+            Some(uid) => {
+                if counts.len() > 1 {
+                    rebuilt.ambiguous.push(title_id);
+                }
+
+                rebuilt.entries.push(Entry {
+                    title_id,
+                    padding: 0,
+                    uid: Uid(uid),
+                });
+            }
+            None => unowned.push(title_id),
+        }
+    }
+
+    rebuilt.entries.sort_by_key(|e| (e.uid, e.title_id));
+    rebuilt.assign(unowned);
+    Ok(rebuilt)
+}
+
+/// Reconstructs uid.sys from the `title` directory of an extracted NAND.
+///
+/// Extracted files carry no SFFS ownership, so UIDs are assigned afresh:
+/// the System Menu first, then every other title in title ID order.
+pub fn rebuild_from_dir(root: impl AsRef<Path>) -> Result<Rebuilt, Error> {
+    let mut rebuilt = Rebuilt::default();
+    rebuilt.assign(NandTree::new(root.as_ref()).title_ids()?);
+    Ok(rebuilt)
+}
+
+impl Rebuilt {
+    /// Appends titles with UIDs following the highest one in use. The System
+    /// Menu gets UID 0x1000 if it is free.
+    fn assign(&mut self, mut title_ids: Vec<u64>) {
+        let first_free = self.entries.iter().all(|e| e.uid != Uid::FIRST_TITLE);
+
+        if let Some(i) = title_ids.iter().position(|&id| id == SYSTEM_MENU_TITLE_ID) {
+            if first_free {
+                title_ids.remove(i);
+                self.entries.insert(
+                    0,
+                    Entry {
+                        title_id: SYSTEM_MENU_TITLE_ID,
+                        padding: 0,
+                        uid: Uid::FIRST_TITLE,
+                    },
+                );
+                self.assigned.push(SYSTEM_MENU_TITLE_ID);
+            }
+        }
+
+        let mut next = self
+            .entries
+            .iter()
+            .map(|e| e.uid.0.saturating_add(1))
+            .max()
+            .unwrap_or(Uid::FIRST_TITLE.0)
+            .max(Uid::FIRST_TITLE.0);
+
+        for title_id in title_ids {
+            self.entries.push(Entry {
+                title_id,
+                padding: 0,
+                uid: Uid(next),
+            });
+            self.assigned.push(title_id);
+            next = next.saturating_add(1);
+        }
+    }
+}
+
+/// The title ID of a path below `/title/<upper>/<lower>`, the title
+/// directory itself included.
+fn title_id_of(path: &str) -> Option<u64> {
+    let mut parts = path.strip_prefix("/title/")?.split('/');
+    let upper = hex_name(parts.next()?)?;
+    let lower = hex_name(parts.next()?)?;
+
+    Some(u64::from(upper) << 32 | u64::from(lower))
+}
@@ -0,0 +1,856 @@
+//! The Wii NAND filesystem (SFFS): superblocks, FAT and FST.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, Write};
+use std::path::Path;
+
+use hmac::{Hmac, Mac};
+use sha1::Sha1;
+
+use crate::nand::{
+    spare_hmac, Keys, Layout, NandImage, CLUSTER_COUNT, CLUSTER_SIZE, PAGES_PER_CLUSTER,
+};
+use crate::Error;
+
+/// First cluster of the superblock area at the end of the NAND.
+pub const SUPERBLOCK_START: u16 = 0x7F00;
+/// Number of clusters making up a superblock.
+pub const SUPERBLOCK_CLUSTERS: u16 = 16;
+/// Number of superblock slots, written to in turn.
+pub const SUPERBLOCK_COUNT: u16 = 16;
+/// Number of entries in the FST.
+pub const FST_ENTRIES: usize = 0x17FF;
+
+/// FAT value marking the last cluster of a file.
+pub const FAT_LAST: u16 = 0xFFFB;
+pub const FAT_RESERVED: u16 = 0xFFFC;
+pub const FAT_BAD: u16 = 0xFFFD;
+pub const FAT_FREE: u16 = 0xFFFE;
+
+/// FST value meaning "no entry", e.g. for the sibling of a last child.
+pub const FST_NONE: u16 = 0xFFFF;
+
+/// Size of a superblock slot.
+pub const SUPERBLOCK_SIZE: usize = SUPERBLOCK_CLUSTERS as usize * CLUSTER_SIZE;
+
+const SUPERBLOCK_MAGIC: &[u8; 4] = b"SFFS";
+const FAT_OFFSET: usize = 0x0C;
+const FST_OFFSET: usize = FAT_OFFSET + CLUSTER_COUNT * 2;
+const FST_ENTRY_SIZE: usize = 0x20;
+
+/// An entry of the filesystem table: a file or a directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FstEntry {
+    /// Raw name, NUL-padded to 12 bytes.
+    pub name: [u8; 12],
+    /// Type in the low two bits, owner/group/other permissions above.
+    pub mode: u8,
+    pub attr: u8,
+    /// First child for directories, first cluster for files.
+    pub sub: u16,
+    /// Next entry in the same directory.
+    pub sib: u16,
+    pub size: u32,
+    /// UID of the owner.
+    pub uid: u32,
+    /// GID of the owner.
+    pub gid: u16,
+    pub x3: u32,
+}
+
+impl FstEntry {
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            name: bytes[0..12].try_into().unwrap(),
+            mode: bytes[0x0C],
+            attr: bytes[0x0D],
+            sub: u16::from_be_bytes(bytes[0x0E..0x10].try_into().unwrap()),
+            sib: u16::from_be_bytes(bytes[0x10..0x12].try_into().unwrap()),
+            size: u32::from_be_bytes(bytes[0x12..0x16].try_into().unwrap()),
+            uid: u32::from_be_bytes(bytes[0x16..0x1A].try_into().unwrap()),
+            gid: u16::from_be_bytes(bytes[0x1A..0x1C].try_into().unwrap()),
+            x3: u32::from_be_bytes(bytes[0x1C..0x20].try_into().unwrap()),
+        }
+    }
+
+    fn to_bytes(&self) -> [u8; FST_ENTRY_SIZE] {
+        let mut bytes = [0; FST_ENTRY_SIZE];
+
+        bytes[0..12].copy_from_slice(&self.name);
+        bytes[0x0C] = self.mode;
+        bytes[0x0D] = self.attr;
+        bytes[0x0E..0x10].copy_from_slice(&self.sub.to_be_bytes());
+        bytes[0x10..0x12].copy_from_slice(&self.sib.to_be_bytes());
+        bytes[0x12..0x16].copy_from_slice(&self.size.to_be_bytes());
+        bytes[0x16..0x1A].copy_from_slice(&self.uid.to_be_bytes());
+        bytes[0x1A..0x1C].copy_from_slice(&self.gid.to_be_bytes());
+        bytes[0x1C..0x20].copy_from_slice(&self.x3.to_be_bytes());
+
+        bytes
+    }
+
+    /// The name as text, up to the first NUL byte.
+    pub fn name(&self) -> String {
+        let len = self.name.iter().position(|&b| b == 0).unwrap_or(12);
+        String::from_utf8_lossy(&self.name[..len]).into_owned()
+    }
+
+    pub fn is_file(&self) -> bool {
+        self.mode & 3 == 1
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.mode & 3 == 2
+    }
+
+    /// Renders the type and owner/group/other permissions in the style of
+    /// `ls -l`, with two characters (`r`, `w`) per class, e.g. `drwrw--`.
+    pub fn mode_string(&self) -> String {
+        let mut result = String::from(match self.mode & 3 {
+            1 => '-',
+            2 => 'd',
+            _ => '?',
+        });
+
+        for shift in [6, 4, 2] {
+            let perms = self.mode >> shift;
+            result.push(if perms & 1 != 0 { 'r' } else { '-' });
+            result.push(if perms & 2 != 0 { 'w' } else { '-' });
+        }
+
+        result
+    }
+}
+
+/// A snapshot of the filesystem metadata: the FAT and the FST.
+#[derive(Debug, Clone)]
+pub struct Superblock {
+    /// First cluster of the slot this superblock was read from.
+    pub cluster: u16,
+    pub generation: u32,
+    pub unknown: u32,
+    /// Next cluster for each cluster, or one of the `FAT_*` markers.
+    pub fat: Vec<u16>,
+    pub fst: Vec<FstEntry>,
+}
+
+impl Superblock {
+    /// Parses the contents of a superblock read from the given slot.
+    pub fn from_bytes(cluster: u16, bytes: &[u8]) -> Result<Self, Error> {
+        if bytes.len() < FST_OFFSET + FST_ENTRIES * FST_ENTRY_SIZE
+            || &bytes[0..4] != SUPERBLOCK_MAGIC
+        {
+            return Err(Error::BadFilesystem);
+        }
+
+        let fat = bytes[FAT_OFFSET..FST_OFFSET]
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+
+        let fst = bytes[FST_OFFSET..FST_OFFSET + FST_ENTRIES * FST_ENTRY_SIZE]
+            .chunks_exact(FST_ENTRY_SIZE)
+            .map(FstEntry::from_bytes)
+            .collect();
+
+        Ok(Self {
+            cluster,
+            generation: u32::from_be_bytes(bytes[4..8].try_into().unwrap()),
+            unknown: u32::from_be_bytes(bytes[8..12].try_into().unwrap()),
+            fat,
+            fst,
+        })
+    }
+
+    /// Encodes the superblock, padded to the full size of a slot.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SUPERBLOCK_SIZE);
+
+        bytes.extend(SUPERBLOCK_MAGIC);
+        bytes.extend(self.generation.to_be_bytes());
+        bytes.extend(self.unknown.to_be_bytes());
+
+        for next in &self.fat {
+            bytes.extend(next.to_be_bytes());
+        }
+
+        for entry in &self.fst {
+            bytes.extend(entry.to_bytes());
+        }
+
+        bytes.resize(SUPERBLOCK_SIZE, 0);
+        bytes
+    }
+
+    /// Follows the FAT from a file's first cluster.
+    pub fn chain(&self, first: u16) -> Result<Vec<u16>, Error> {
+        let mut chain = vec![];
+        let mut cluster = first;
+
+        while cluster != FAT_LAST {
+            if cluster as usize >= CLUSTER_COUNT || chain.len() >= CLUSTER_COUNT {
+                return Err(Error::BadFilesystem);
+            }
+
+            chain.push(cluster);
+            cluster = self.fat[cluster as usize];
+        }
+
+        Ok(chain)
+    }
+
+    /// Lists the FST indices of the entries in a directory, in FST order.
+    pub fn children(&self, dir: usize) -> Result<Vec<usize>, Error> {
+        let entry = self.fst.get(dir).ok_or(Error::BadFilesystem)?;
+
+        if !entry.is_dir() {
+            return Err(Error::BadFilesystem);
+        }
+
+        let mut children = vec![];
+        let mut child = entry.sub;
+
+        while child != FST_NONE {
+            if child as usize >= self.fst.len() || children.len() >= FST_ENTRIES {
+                return Err(Error::BadFilesystem);
+            }
+
+            children.push(child as usize);
+            child = self.fst[child as usize].sib;
+        }
+
+        Ok(children)
+    }
+
+    /// Finds the FST index of an absolute path such as `/sys/uid.sys`.
+    pub fn lookup(&self, path: &str) -> Result<usize, Error> {
+        let mut index = 0;
+
+        for component in path.split('/').filter(|c| !c.is_empty()) {
+            if !self.fst[index].is_dir() {
+                return Err(Error::NotFound(path.to_owned()));
+            }
+
+            index = self
+                .children(index)?
+                .into_iter()
+                .find(|&child| self.fst[child].name() == component)
+                .ok_or_else(|| Error::NotFound(path.to_owned()))?;
+        }
+
+        Ok(index)
+    }
+
+    /// Lists every entry below a directory, depth first, as pairs of
+    /// absolute path and FST index. The directory itself is not included.
+    pub fn walk(&self, dir: usize) -> Result<Vec<(String, usize)>, Error> {
+        let mut result = vec![];
+        let prefix = if dir == 0 {
+            String::new()
+        } else {
+            self.path_of(dir)?
+        };
+
+        self.walk_into(dir, &prefix, 0, &mut result)?;
+        Ok(result)
+    }
+
+    fn walk_into(
+        &self,
+        dir: usize,
+        prefix: &str,
+        depth: usize,
+        result: &mut Vec<(String, usize)>,
+    ) -> Result<(), Error> {
+        // A tree deeper or larger than the FST can only come from a loop.
+        if depth > FST_ENTRIES || result.len() > FST_ENTRIES {
+            return Err(Error::BadFilesystem);
+        }
+
+        for child in self.children(dir)? {
+            let path = format!("{prefix}/{}", self.fst[child].name());
+            result.push((path.clone(), child));
+
+            if self.fst[child].is_dir() {
+                self.walk_into(child, &path, depth + 1, result)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Builds the absolute path of an FST entry by searching from the root.
+    pub fn path_of(&self, index: usize) -> Result<String, Error> {
+        if index == 0 {
+            return Ok("/".to_owned());
+        }
+
+        self.walk(0)?
+            .into_iter()
+            .find(|&(_, i)| i == index)
+            .map(|(path, _)| path)
+            .ok_or(Error::BadFilesystem)
+    }
+
+    /// Whether the superblock looks usable: the first FST entry must be the
+    /// root directory and its tree must be free of loops.
+    pub fn is_valid(&self) -> bool {
+        self.fst.first().is_some_and(|root| root.is_dir()) && self.walk(0).is_ok()
+    }
+}
+
+/// The Wii NAND filesystem (SFFS) of a NAND image.
+pub struct Sffs<R> {
+    nand: NandImage<R>,
+    superblock: Superblock,
+}
+
+impl Sffs<File> {
+    /// Opens a nand.bin and mounts its filesystem. `keys` must be given
+    /// unless the dump has a keys.bin block appended.
+    pub fn open(path: impl AsRef<Path>, keys: Option<Keys>) -> Result<Self, Error> {
+        Self::new(NandImage::open(path, keys)?)
+    }
+}
+
+impl<R: Read + Seek> Sffs<R> {
+    /// Mounts the filesystem using the newest valid superblock.
+    ///
+    /// Superblock slots are tried from the highest generation down, so that
+    /// a superblock left half-written by an interrupted update is skipped. In
+    /// dumps with ECC data, a superblock must also match its stored HMAC; if
+    /// none does, the newest one that parses is used so that the damage can
+    /// still be inspected.
+    pub fn new(mut nand: NandImage<R>) -> Result<Self, Error> {
+        let mut unauthenticated = None;
+
+        for (_, cluster) in superblock_slots(&mut nand)? {
+            let Ok(bytes) = read_superblock_bytes(&mut nand, cluster) else {
+                continue;
+            };
+
+            match Superblock::from_bytes(cluster, &bytes) {
+                Ok(superblock) if superblock.is_valid() => {
+                    if superblock_hmac_matches(&mut nand, cluster, &bytes)? {
+                        return Ok(Self { nand, superblock });
+                    }
+
+                    unauthenticated.get_or_insert(superblock);
+                }
+                _ => {}
+            }
+        }
+
+        match unauthenticated {
+            Some(superblock) => Ok(Self { nand, superblock }),
+            None => Err(Error::NoSuperblock),
+        }
+    }
+
+    pub fn superblock(&self) -> &Superblock {
+        &self.superblock
+    }
+
+    pub fn nand(&mut self) -> &mut NandImage<R> {
+        &mut self.nand
+    }
+
+    /// The FST entry at the given index.
+    pub fn entry(&self, index: usize) -> Option<&FstEntry> {
+        self.superblock.fst.get(index)
+    }
+
+    /// Lists a directory by absolute path, as pairs of FST index and entry.
+    pub fn read_dir(&self, path: &str) -> Result<Vec<(usize, &FstEntry)>, Error> {
+        let index = self.superblock.lookup(path)?;
+
+        if !self.superblock.fst[index].is_dir() {
+            return Err(Error::NotFound(path.to_owned()));
+        }
+
+        Ok(self
+            .superblock
+            .children(index)?
+            .into_iter()
+            .map(|i| (i, &self.superblock.fst[i]))
+            .collect())
+    }
+
+    /// Reads a file by absolute path.
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, Error> {
+        let index = self.superblock.lookup(path)?;
+
+        if !self.superblock.fst[index].is_file() {
+            return Err(Error::NotFound(path.to_owned()));
+        }
+
+        self.read_entry(index)
+    }
+
+    /// Reads the file at the given FST index, cluster by cluster.
+    ///
+    /// In dumps with ECC data, each cluster is ECC-corrected and must match
+    /// its stored HMAC, or else reading fails with [`Error::BadHmac`], as
+    /// reported by [`check_nand`](crate::check_nand).
+    pub fn read_entry(&mut self, index: usize) -> Result<Vec<u8>, Error> {
+        let entry = self.entry(index).ok_or(Error::BadFilesystem)?.clone();
+
+        if !entry.is_file() {
+            return Err(Error::BadFilesystem);
+        }
+
+        let size = entry.size as usize;
+        let chain = self.file_clusters(index)?;
+        let mut data = Vec::with_capacity(size);
+
+        for (i, cluster) in chain.into_iter().enumerate() {
+            let (plain, stored) = self.nand.read_cluster_with_hmac(cluster)?;
+
+            if let Some(stored) = stored {
+                let hmac = cluster_hmac(self.nand.keys(), &entry, index, i, &plain);
+
+                if hmac != stored.0 && hmac != stored.1 {
+                    return Err(Error::BadHmac { cluster });
+                }
+            }
+
+            data.extend(plain);
+        }
+
+        if data.len() < size {
+            return Err(Error::BadFilesystem);
+        }
+
+        data.truncate(size);
+        Ok(data)
+    }
+
+    /// The clusters holding the file at the given FST index, in order.
+    /// Empty files have no clusters.
+    pub fn file_clusters(&self, index: usize) -> Result<Vec<u16>, Error> {
+        let entry = self.entry(index).ok_or(Error::BadFilesystem)?;
+
+        if entry.size == 0 {
+            return Ok(vec![]);
+        }
+
+        self.superblock.chain(entry.sub)
+    }
+}
+
+/// Lists the superblock slots holding an SFFS superblock, as pairs of
+/// generation and first cluster, newest first.
+pub fn superblock_slots<R: Read + Seek>(nand: &mut NandImage<R>) -> Result<Vec<(u32, u16)>, Error> {
+    let mut slots = vec![];
+
+    for slot in 0..SUPERBLOCK_COUNT {
+        let cluster = SUPERBLOCK_START + slot * SUPERBLOCK_CLUSTERS;
+        let header = nand.read_page(cluster as usize * PAGES_PER_CLUSTER)?;
+
+        if &header[0..4] == SUPERBLOCK_MAGIC {
+            let generation = u32::from_be_bytes(header[4..8].try_into().unwrap());
+            slots.push((generation, cluster));
+        }
+    }
+
+    slots.sort_by(|a, b| b.cmp(a));
+    Ok(slots)
+}
+
+/// Reads and parses the superblock starting at the given cluster.
+pub fn read_superblock<R: Read + Seek>(
+    nand: &mut NandImage<R>,
+    cluster: u16,
+) -> Result<Superblock, Error> {
+    Superblock::from_bytes(cluster, &read_superblock_bytes(nand, cluster)?)
+}
+
+fn read_superblock_bytes<R: Read + Seek>(
+    nand: &mut NandImage<R>,
+    cluster: u16,
+) -> Result<Vec<u8>, Error> {
+    let mut bytes = Vec::with_capacity(SUPERBLOCK_SIZE);
+
+    for c in cluster..cluster + SUPERBLOCK_CLUSTERS {
+        bytes.extend(nand.read_cluster_raw(c)?);
+    }
+
+    Ok(bytes)
+}
+
+/// Whether the superblock in the slot starting at `cluster` matches the HMAC
+/// stored in the spare area of its last cluster. Dumps without ECC data have
+/// no HMACs and always match.
+fn superblock_hmac_matches<R: Read + Seek>(
+    nand: &mut NandImage<R>,
+    cluster: u16,
+    bytes: &[u8],
+) -> Result<bool, Error> {
+    if nand.layout() != Layout::WithEcc {
+        return Ok(true);
+    }
+
+    let (_, spares) = nand.read_cluster_with_spare(cluster + SUPERBLOCK_CLUSTERS - 1)?;
+    let stored = spare_hmac(&spares[6], &spares[7]);
+    let hmac = superblock_hmac(nand.keys(), cluster, bytes);
+
+    Ok(hmac == stored.0 || hmac == stored.1)
+}
+
+impl<R: Read + Write + Seek> Sffs<R> {
+    /// Replaces the contents of an existing file.
+    ///
+    /// As IOS does, the new data is written to free clusters and the change
+    /// is committed by writing the updated FAT and FST as a new superblock
+    /// generation in the next slot, leaving the previous one intact. Clusters
+    /// are encrypted and their HMAC and ECC data recomputed.
+    pub fn replace_file(&mut self, path: &str, data: &[u8]) -> Result<(), Error> {
+        let index = self.superblock.lookup(path)?;
+
+        if !self.superblock.fst[index].is_file() {
+            return Err(Error::NotFound(path.to_owned()));
+        }
+
+        let old_chain = self.file_clusters(index)?;
+        let mut superblock = self.superblock.clone();
+
+        let count = data.len().div_ceil(CLUSTER_SIZE);
+        let chain = free_clusters(&superblock, count)?;
+
+        for (i, &cluster) in chain.iter().enumerate() {
+            superblock.fat[cluster as usize] = chain.get(i + 1).copied().unwrap_or(FAT_LAST);
+        }
+
+        for cluster in old_chain {
+            superblock.fat[cluster as usize] = FAT_FREE;
+        }
+
+        let entry = &mut superblock.fst[index];
+        entry.size = data.len() as u32;
+        entry.sub = chain.first().copied().unwrap_or(FST_NONE);
+        let entry = entry.clone();
+
+        for (i, (&cluster, chunk)) in chain.iter().zip(data.chunks(CLUSTER_SIZE)).enumerate() {
+            let mut plain = chunk.to_vec();
+            plain.resize(CLUSTER_SIZE, 0);
+
+            let hmac = cluster_hmac(self.nand.keys(), &entry, index, i, &plain);
+            self.nand.write_cluster(cluster, &plain, Some(&hmac))?;
+        }
+
+        self.commit(superblock)
+    }
+
+    /// Writes a superblock as the next generation, in the slot after the
+    /// current one.
+    fn commit(&mut self, mut superblock: Superblock) -> Result<(), Error> {
+        let slot = (self.superblock.cluster - SUPERBLOCK_START) / SUPERBLOCK_CLUSTERS;
+        let next = SUPERBLOCK_START + (slot + 1) % SUPERBLOCK_COUNT * SUPERBLOCK_CLUSTERS;
+
+        superblock.cluster = next;
+        superblock.generation = self.superblock.generation.wrapping_add(1);
+
+        let bytes = superblock.to_bytes();
+        let hmac = superblock_hmac(self.nand.keys(), next, &bytes);
+
+        for (i, chunk) in bytes.chunks_exact(CLUSTER_SIZE).enumerate() {
+            let last = i == SUPERBLOCK_CLUSTERS as usize - 1;
+            self.nand
+                .write_cluster_raw(next + i as u16, chunk, last.then_some(&hmac))?;
+        }
+
+        self.superblock = superblock;
+        Ok(())
+    }
+}
+
+/// Writes a copy of a NAND dump to `output` with one of its files replaced,
+/// see [`Sffs::replace_file`]. The original dump is never modified, and
+/// `output` must not exist yet. Nothing is left behind if the write fails.
+pub fn replace_nand_file(
+    input: impl AsRef<Path>,
+    output: impl AsRef<Path>,
+    keys: Option<Keys>,
+    path: &str,
+    data: &[u8],
+) -> Result<(), Error> {
+    let output = output.as_ref();
+
+    let mut out = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create_new(true)
+        .open(output)?;
+    let result = io::copy(&mut File::open(input)?, &mut out)
+        .map_err(Error::from)
+        .and_then(|_| Sffs::new(NandImage::new(out, keys)?))
+        .and_then(|mut sffs| sffs.replace_file(path, data));
+
+    if result.is_err() {
+        let _ = fs::remove_file(output);
+    }
+
+    result
+}
+
+/// Picks `count` free clusters, lowest first.
+fn free_clusters(superblock: &Superblock, count: usize) -> Result<Vec<u16>, Error> {
+    let free: Vec<u16> = (0..SUPERBLOCK_START)
+        .filter(|&c| superblock.fat[c as usize] == FAT_FREE)
+        .take(count)
+        .collect();
+
+    if free.len() < count {
+        return Err(Error::NandFull);
+    }
+
+    Ok(free)
+}
+
+/// Computes the HMAC of a file cluster.
+///
+/// The HMAC covers a 0x40-byte salt followed by the decrypted cluster. The
+/// salt holds the owner's UID, the file name, the position of the cluster in
+/// the file, the FST index of the file and its `x3` field, zero-padded.
+pub fn cluster_hmac(
+    keys: &Keys,
+    entry: &FstEntry,
+    fst_index: usize,
+    chain_index: usize,
+    data: &[u8],
+) -> [u8; 20] {
+    let mut salt = [0; 0x40];
+
+    salt[0..4].copy_from_slice(&entry.uid.to_be_bytes());
+    salt[4..16].copy_from_slice(&entry.name);
+    salt[16..20].copy_from_slice(&(chain_index as u32).to_be_bytes());
+    salt[20..24].copy_from_slice(&(fst_index as u32).to_be_bytes());
+    salt[24..28].copy_from_slice(&entry.x3.to_be_bytes());
+
+    hmac(keys, &salt, data)
+}
+
+/// Computes the HMAC of a superblock. The salt is zero except for the first
+/// cluster of the superblock's slot, at offset 0x12.
+pub fn superblock_hmac(keys: &Keys, cluster: u16, data: &[u8]) -> [u8; 20] {
+    let mut salt = [0; 0x40];
+    salt[0x12..0x14].copy_from_slice(&cluster.to_be_bytes());
+
+    hmac(keys, &salt, data)
+}
+
+fn hmac(keys: &Keys, salt: &[u8], data: &[u8]) -> [u8; 20] {
+    let mut mac = Hmac::<Sha1>::new_from_slice(&keys.hmac).expect("HMAC accepts any key size");
+    mac.update(salt);
+    mac.update(data);
+    mac.finalize().into_bytes().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::SeekFrom;
+
+    use super::*;
+    use crate::check_nand;
+    use crate::nand::{PAGE_SIZE, SPARE_SIZE};
+
+    const CLUSTER_STRIDE: usize = PAGES_PER_CLUSTER * (PAGE_SIZE + SPARE_SIZE);
+
+    const KEYS: Keys = Keys {
+        aes: [0x11; 16],
+        hmac: [0x22; 20],
+    };
+
+    /// A NAND dump with ECC data held in memory, where clusters never written
+    /// read as erased.
+    struct SparseNand {
+        clusters: HashMap<usize, Vec<u8>>,
+        position: usize,
+    }
+
+    impl SparseNand {
+        const LEN: usize = CLUSTER_COUNT * CLUSTER_STRIDE;
+
+        fn new() -> Self {
+            Self {
+                clusters: HashMap::new(),
+                position: 0,
+            }
+        }
+    }
+
+    impl Read for SparseNand {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let (cluster, offset) = (
+                self.position / CLUSTER_STRIDE,
+                self.position % CLUSTER_STRIDE,
+            );
+            let len = buf
+                .len()
+                .min(CLUSTER_STRIDE - offset)
+                .min(Self::LEN - self.position);
+
+            match self.clusters.get(&cluster) {
+                Some(data) => buf[..len].copy_from_slice(&data[offset..offset + len]),
+                None => buf[..len].fill(0xFF),
+            }
+
+            self.position += len;
+            Ok(len)
+        }
+    }
+
+    impl Write for SparseNand {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let (cluster, offset) = (
+                self.position / CLUSTER_STRIDE,
+                self.position % CLUSTER_STRIDE,
+            );
+            let len = buf.len().min(CLUSTER_STRIDE - offset);
+
+            self.clusters
+                .entry(cluster)
+                .or_insert_with(|| vec![0xFF; CLUSTER_STRIDE])[offset..offset + len]
+                .copy_from_slice(&buf[..len]);
+
+            self.position += len;
+            Ok(len)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Seek for SparseNand {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.position = match pos {
+                SeekFrom::Start(n) => n as usize,
+                SeekFrom::End(n) => (Self::LEN as i64 + n) as usize,
+                SeekFrom::Current(n) => (self.position as i64 + n) as usize,
+            };
+
+            Ok(self.position as u64)
+        }
+    }
+
+    fn fst_entry(name: &str, mode: u8, sub: u16, size: u32) -> FstEntry {
+        let mut raw = [0; 12];
+        raw[..name.len()].copy_from_slice(name.as_bytes());
+
+        FstEntry {
+            name: raw,
+            mode,
+            attr: 0,
+            sub,
+            sib: FST_NONE,
+            size,
+            uid: 0,
+            gid: 0,
+            x3: 0,
+        }
+    }
+
+    /// Formats a NAND with `/sys/uid.sys` holding `uid_sys` in cluster 0,
+    /// committed as generation 1 in the first superblock slot.
+    fn format(nand: &mut SparseNand, uid_sys: &[u8]) {
+        let mut image = NandImage::new(nand, Some(KEYS)).unwrap();
+
+        let mut fat = vec![FAT_FREE; CLUSTER_COUNT];
+        fat[0] = FAT_LAST;
+        fat[SUPERBLOCK_START as usize..].fill(FAT_RESERVED);
+
+        let mut fst = vec![fst_entry("", 0, FST_NONE, 0); FST_ENTRIES];
+        fst[0] = fst_entry("/", 0x16, 1, 0);
+        fst[1] = fst_entry("sys", 0x16, 2, 0);
+        fst[2] = fst_entry("uid.sys", 0x15, 0, uid_sys.len() as u32);
+
+        let mut data = uid_sys.to_vec();
+        data.resize(CLUSTER_SIZE, 0);
+        let hmac = cluster_hmac(&KEYS, &fst[2], 2, 0, &data);
+        image.write_cluster(0, &data, Some(&hmac)).unwrap();
+
+        let superblock = Superblock {
+            cluster: SUPERBLOCK_START,
+            generation: 1,
+            unknown: 0,
+            fat,
+            fst,
+        };
+        let bytes = superblock.to_bytes();
+        let hmac = superblock_hmac(&KEYS, SUPERBLOCK_START, &bytes);
+
+        for (i, chunk) in bytes.chunks_exact(CLUSTER_SIZE).enumerate() {
+            let last = i == SUPERBLOCK_CLUSTERS as usize - 1;
+            image
+                .write_cluster_raw(SUPERBLOCK_START + i as u16, chunk, last.then_some(&hmac))
+                .unwrap();
+        }
+    }
+
+    fn mount(nand: &mut SparseNand) -> Sffs<&mut SparseNand> {
+        Sffs::new(NandImage::new(nand, Some(KEYS)).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn replace_file_commits_next_generation() {
+        let mut nand = SparseNand::new();
+        format(&mut nand, &[0xAA; 24]);
+
+        assert_eq!(
+            mount(&mut nand).read_file("/sys/uid.sys").unwrap(),
+            [0xAA; 24]
+        );
+
+        // Large enough to need a second cluster.
+        let uid_sys: Vec<u8> = (0..CLUSTER_SIZE + 36).map(|i| i as u8).collect();
+        mount(&mut nand)
+            .replace_file("/sys/uid.sys", &uid_sys)
+            .unwrap();
+
+        let mut sffs = mount(&mut nand);
+        assert_eq!(sffs.superblock().generation, 2);
+        assert_eq!(
+            sffs.superblock().cluster,
+            SUPERBLOCK_START + SUPERBLOCK_CLUSTERS
+        );
+        assert_eq!(sffs.read_file("/sys/uid.sys").unwrap(), uid_sys);
+
+        let report = check_nand(&mut sffs).unwrap();
+        assert!(report.is_ok(), "{report:?}");
+        assert!(report.ecc_errors.is_empty());
+        assert!(report.superblocks.iter().all(|s| s.hmac_ok));
+        assert_eq!(report.superblocks.len(), 2);
+    }
+
+    #[test]
+    fn read_file_corrects_ecc_and_checks_hmac() {
+        let mut nand = SparseNand::new();
+        format(&mut nand, &[0xAA; 24]);
+
+        // One flipped bit is corrected, and so is the verdict of the check.
+        nand.clusters.get_mut(&0).unwrap()[5] ^= 0x10;
+        let mut sffs = mount(&mut nand);
+        assert_eq!(sffs.read_file("/sys/uid.sys").unwrap(), [0xAA; 24]);
+        let report = check_nand(&mut sffs).unwrap();
+        assert!(report.is_ok(), "{report:?}");
+        assert_eq!(report.ecc_errors.len(), 1);
+
+        nand.clusters.get_mut(&0).unwrap()[6] ^= 0x01;
+        assert!(matches!(
+            mount(&mut nand).read_file("/sys/uid.sys"),
+            Err(Error::UncorrectableEcc { cluster: 0 })
+        ));
+
+        // Valid ECC over data that no longer matches the HMAC.
+        let mut image = NandImage::new(&mut nand, Some(KEYS)).unwrap();
+        image
+            .write_cluster(0, &[0xBB; CLUSTER_SIZE], Some(&[0; 20]))
+            .unwrap();
+        assert!(matches!(
+            mount(&mut nand).read_file("/sys/uid.sys"),
+            Err(Error::BadHmac { cluster: 0 })
+        ));
+    }
+}
@@ -0,0 +1,99 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek};
+use std::path::Path;
+
+use crate::nand::Keys;
+use crate::sffs::Sffs;
+use crate::{Error, NandTree};
+
+/// A Wii NAND to read files from by their NAND path: an extracted NAND
+/// directory tree or the filesystem of a NAND dump.
+pub enum NandSource<R = File> {
+    Tree(NandTree),
+    Nand(Sffs<R>),
+}
+
+impl NandSource<File> {
+    /// Opens a directory as an extracted NAND, or else a file as a nand.bin.
+    /// `keys` are only used for NAND dumps, see [`Sffs::open`].
+    pub fn open(path: impl AsRef<Path>, keys: Option<Keys>) -> Result<Self, Error> {
+        let path = path.as_ref();
+
+        if path.is_dir() {
+            Ok(Self::Tree(NandTree::new(path)))
+        } else {
+            Ok(Self::Nand(Sffs::open(path, keys)?))
+        }
+    }
+}
+
+impl<R: Read + Seek> NandSource<R> {
+    /// The extracted NAND, if this is one.
+    pub fn tree(&self) -> Option<&NandTree> {
+        match self {
+            Self::Tree(tree) => Some(tree),
+            Self::Nand(_) => None,
+        }
+    }
+
+    /// Reads a file by absolute NAND path, e.g. `/sys/uid.sys`. Fails with
+    /// [`Error::NotFound`] if it does not exist.
+    pub fn read_file(&mut self, path: &str) -> Result<Vec<u8>, Error> {
+        match self {
+            Self::Tree(tree) => fs::read(tree.path(path)).map_err(|e| match e.kind() {
+                io::ErrorKind::NotFound => Error::NotFound(path.to_owned()),
+                _ => Error::from(e),
+            }),
+            Self::Nand(sffs) => sffs.read_file(path),
+        }
+    }
+
+    /// Lists the NAND paths of every file below a directory, recursively and
+    /// in path order. A missing directory has no files.
+    pub fn files(&self, dir: &str) -> Result<Vec<String>, Error> {
+        let mut files = vec![];
+
+        match self {
+            Self::Tree(tree) => {
+                let path = tree.path(dir);
+
+                if path.is_dir() {
+                    walk_dir(&path, dir.trim_end_matches('/'), &mut files)?;
+                }
+            }
+            Self::Nand(sffs) => {
+                let superblock = sffs.superblock();
+
+                let dir = match superblock.lookup(dir) {
+                    Ok(index) => index,
+                    Err(Error::NotFound(_)) => return Ok(files),
+                    Err(e) => return Err(e),
+                };
+
+                for (path, index) in superblock.walk(dir)? {
+                    if superblock.fst[index].is_file() {
+                        files.push(path);
+                    }
+                }
+            }
+        }
+
+        files.sort();
+        Ok(files)
+    }
+}
+
+fn walk_dir(dir: &Path, nand_dir: &str, files: &mut Vec<String>) -> Result<(), Error> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let nand_path = format!("{nand_dir}/{}", entry.file_name().to_string_lossy());
+
+        if entry.file_type()?.is_dir() {
+            walk_dir(&entry.path(), &nand_path, files)?;
+        } else {
+            files.push(nand_path);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,163 @@
+use std::fmt::Display;
+
+use crate::Tmd;
+
+/// Known retail System Menu releases: title version, version and region
+/// letter of the release. Versions above 518 are the vWii System Menu of the
+/// Wii U.
+const VERSIONS: [(u16, &str, char); 46] = [
+    (33, "1.0", 'U'),
+    (128, "2.0", 'J'),
+    (97, "2.0", 'U'),
+    (130, "2.0", 'E'),
+    (162, "2.1", 'E'),
+    (192, "2.2", 'J'),
+    (193, "2.2", 'U'),
+    (194, "2.2", 'E'),
+    (224, "3.0", 'J'),
+    (225, "3.0", 'U'),
+    (226, "3.0", 'E'),
+    (256, "3.1", 'J'),
+    (257, "3.1", 'U'),
+    (258, "3.1", 'E'),
+    (288, "3.2", 'J'),
+    (289, "3.2", 'U'),
+    (290, "3.2", 'E'),
+    (352, "3.3", 'J'),
+    (353, "3.3", 'U'),
+    (354, "3.3", 'E'),
+    (326, "3.3", 'K'),
+    (384, "3.4", 'J'),
+    (385, "3.4", 'U'),
+    (386, "3.4", 'E'),
+    (390, "3.5", 'K'),
+    (416, "4.0", 'J'),
+    (417, "4.0", 'U'),
+    (418, "4.0", 'E'),
+    (448, "4.1", 'J'),
+    (449, "4.1", 'U'),
+    (450, "4.1", 'E'),
+    (454, "4.1", 'K'),
+    (480, "4.2", 'J'),
+    (481, "4.2", 'U'),
+    (482, "4.2", 'E'),
+    (486, "4.2", 'K'),
+    (512, "4.3", 'J'),
+    (513, "4.3", 'U'),
+    (514, "4.3", 'E'),
+    (518, "4.3", 'K'),
+    (544, "4.3", 'J'),
+    (545, "4.3", 'U'),
+    (546, "4.3", 'E'),
+    (608, "4.3", 'J'),
+    (609, "4.3", 'U'),
+    (610, "4.3", 'E'),
+];
+
+/// A retail System Menu release, e.g. 4.3U.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemMenuVersion {
+    /// Title version of the System Menu's TMD.
+    pub title_version: u16,
+    /// Version as shown in the Wii Settings, e.g. `4.3`.
+    pub version: &'static str,
+    /// Region letter: `J`, `U`, `E` or `K`.
+    pub region: char,
+}
+
+impl SystemMenuVersion {
+    /// Looks up a System Menu title version in the table of retail
+    /// releases.
+    pub fn from_title_version(title_version: u16) -> Option<Self> {
+        VERSIONS.iter().find(|(v, _, _)| *v == title_version).map(
+            |&(title_version, version, region)| Self {
+                title_version,
+                version,
+                region,
+            },
+        )
+    }
+
+    /// Identifies the System Menu release of a TMD. The region is taken
+    /// from the TMD, falling back to the one of the retail release for
+    /// region-free TMDs.
+    pub fn from_tmd(tmd: &Tmd) -> Option<Self> {
+        let version = Self::from_title_version(tmd.title_version)?;
+
+        let region = match tmd.region {
+            0 => 'J',
+            1 => 'U',
+            2 => 'E',
+            4 => 'K',
+            _ => version.region,
+        };
+
+        Some(Self { region, ..version })
+    }
+
+    /// Name of the region the release is for.
+    pub fn region_name(&self) -> &'static str {
+        match self.region {
+            'J' => "NTSC-J",
+            'U' => "NTSC-U",
+            'E' => "PAL",
+            'K' => "NTSC-K",
+            _ => "unknown",
+        }
+    }
+
+    /// Whether this is the vWii System Menu of a Wii U.
+    pub fn is_vwii(&self) -> bool {
+        self.title_version > 518
+    }
+}
+
+impl Display for SystemMenuVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}{}", self.version, self.region)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Signature;
+
+    fn tmd(title_version: u16, region: u16) -> Tmd {
+        Tmd {
+            signature: Signature {
+                signature_type: 0x10001,
+                signature: vec![],
+                issuer: String::new(),
+                hash: [0; 20],
+            },
+            sys_version: 0,
+            title_id: crate::SYSTEM_MENU_TITLE_ID,
+            title_type: 1,
+            group_id: 0,
+            region,
+            access_rights: 0,
+            title_version,
+            boot_index: 0,
+            contents: vec![],
+        }
+    }
+
+    #[test]
+    fn region_comes_from_the_tmd() {
+        let v = SystemMenuVersion::from_tmd(&tmd(33, 0)).unwrap();
+        assert_eq!(v.to_string(), "1.0J");
+        assert_eq!(v.region_name(), "NTSC-J");
+
+        let v = SystemMenuVersion::from_tmd(&tmd(513, 1)).unwrap();
+        assert_eq!(v.to_string(), "4.3U");
+        assert!(!v.is_vwii());
+
+        // Region free: the region of the retail release.
+        let v = SystemMenuVersion::from_tmd(&tmd(610, 3)).unwrap();
+        assert_eq!(v.to_string(), "4.3E");
+        assert!(v.is_vwii());
+
+        assert_eq!(SystemMenuVersion::from_tmd(&tmd(1, 1)), None);
+    }
+}
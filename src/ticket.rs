@@ -0,0 +1,255 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io::{Read, Seek};
+use std::path::Path;
+
+use crate::{Entry, Error, NandSource, Signature};
+
+/// Size of the signed part of a ticket, from the issuer to the limits.
+const BODY_SIZE: usize = 0x164;
+
+/// A usage limit of a ticket, as used for trial titles.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Limit {
+    /// 1 for play time in seconds, 4 for a number of launches.
+    pub kind: u32,
+    pub value: u32,
+}
+
+/// A ticket, granting the right to decrypt and launch a title.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ticket {
+    pub signature: Signature,
+    /// Title key, encrypted with the common key selected by
+    /// [`Ticket::common_key_index`] and the title ID.
+    pub encrypted_title_key: [u8; 16],
+    pub ticket_id: u64,
+    /// Console the ticket is tied to, or 0 for any console.
+    pub console_id: u32,
+    pub title_id: u64,
+    /// 0 for the common key, 1 for the Korean key, 2 for the vWii key.
+    pub common_key_index: u8,
+    /// One bit per content index, set when the content may be accessed.
+    pub content_access: [u8; 64],
+    /// Enabled usage limits.
+    pub limits: Vec<Limit>,
+    /// Size of the ticket, signature included.
+    pub size: usize,
+}
+
+impl Ticket {
+    /// Decodes a ticket. Data after the ticket is ignored.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let offset = Signature::body_offset(bytes)?;
+        let body = bytes
+            .get(offset..offset + BODY_SIZE)
+            .ok_or(Error::ReadError)?;
+
+        let u32_at =
+            |offset: usize| u32::from_be_bytes(body[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_be_bytes(body[offset..offset + 8].try_into().unwrap());
+
+        Ok(Self {
+            signature: Signature::read(bytes, BODY_SIZE)?,
+            encrypted_title_key: body[0x7F..0x8F].try_into().unwrap(),
+            ticket_id: u64_at(0x90),
+            console_id: u32_at(0x98),
+            title_id: u64_at(0x9C),
+            common_key_index: body[0xB1],
+            content_access: body[0xE2..0x122].try_into().unwrap(),
+            limits: (0..8)
+                .map(|i| Limit {
+                    kind: u32_at(0x124 + i * 8),
+                    value: u32_at(0x128 + i * 8),
+                })
+                .filter(|l| l.kind != 0)
+                .collect(),
+            size: offset + BODY_SIZE,
+        })
+    }
+
+    /// Reads the first ticket of a .tik file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    /// Whether the ticket only works on one console.
+    pub fn is_personalized(&self) -> bool {
+        self.console_id != 0
+    }
+
+    /// Whether the content with the given index may be accessed.
+    pub fn can_access(&self, index: u16) -> bool {
+        let index = index as usize;
+        self.content_access
+            .get(index / 8)
+            .is_some_and(|byte| byte & (0x80 >> (index % 8)) != 0)
+    }
+}
+
+/// Decodes every ticket of a .tik file, which can hold several one after
+/// the other.
+pub fn parse_tickets(mut bytes: &[u8]) -> Result<Vec<Ticket>, Error> {
+    let mut tickets = vec![];
+
+    while !bytes.is_empty() {
+        let ticket = Ticket::from_bytes(bytes)?;
+        bytes = &bytes[ticket.size..];
+        tickets.push(ticket);
+    }
+
+    Ok(tickets)
+}
+
+/// The path of a title's ticket on the NAND.
+pub fn ticket_path(title_id: u64) -> String {
+    format!("/ticket/{:08x}/{:08x}.tik", title_id >> 32, title_id as u32)
+}
+
+/// The tickets found on a NAND, see [`load_tickets`].
+#[derive(Debug)]
+pub struct NandTickets {
+    /// Every ticket read, sorted by title ID.
+    pub tickets: Vec<Ticket>,
+    /// Ticket files that could not be read or decoded, with the reason.
+    pub unreadable: Vec<(String, Error)>,
+}
+
+/// Reads every .tik file below /ticket of a NAND dump or extracted NAND.
+/// Unreadable ticket files are collected rather than failing the whole
+/// read.
+pub fn load_tickets<R: Read + Seek>(source: &mut NandSource<R>) -> Result<NandTickets, Error> {
+    let mut tickets = vec![];
+    let mut unreadable = vec![];
+
+    for path in source.files("/ticket")? {
+        if !path.ends_with(".tik") {
+            continue;
+        }
+
+        match source.read_file(&path).and_then(|b| parse_tickets(&b)) {
+            Ok(t) => tickets.extend(t),
+            Err(e) => unreadable.push((path, e)),
+        }
+    }
+
+    tickets.sort_by_key(|t| t.title_id);
+    Ok(NandTickets {
+        tickets,
+        unreadable,
+    })
+}
+
+/// How the tickets on a NAND match the titles of uid.sys.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TicketReport {
+    /// Entries of uid.sys without a ticket, in uid.sys order.
+    pub missing: Vec<Entry>,
+    /// Tickets for titles missing from uid.sys, which were never installed
+    /// or launched.
+    pub unused: Vec<Ticket>,
+}
+
+/// Correlates tickets with the entries of uid.sys.
+pub fn ticket_report(entries: &[Entry], tickets: &[Ticket]) -> TicketReport {
+    let ticketed: HashSet<u64> = tickets.iter().map(|t| t.title_id).collect();
+    let listed: HashSet<u64> = entries.iter().map(|e| e.title_id).collect();
+
+    TicketReport {
+        missing: entries
+            .iter()
+            .filter(|e| !ticketed.contains(&e.title_id))
+            .copied()
+            .collect(),
+        unused: tickets
+            .iter()
+            .filter(|t| !listed.contains(&t.title_id))
+            .cloned()
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Uid;
+
+    /// An RSA-2048 signed ticket for a title, with a 30-launch limit.
+    fn ticket(title_id: u64, console_id: u32) -> Vec<u8> {
+        let mut bytes = vec![0; 0x140 + BODY_SIZE];
+        bytes[0..4].copy_from_slice(&0x10001u32.to_be_bytes());
+
+        let body = &mut bytes[0x140..];
+        body[..26].copy_from_slice(b"Root-CA00000001-XS00000003");
+        body[0x7F..0x8F].copy_from_slice(&[0x5A; 16]);
+        body[0x90..0x98].copy_from_slice(&0x0102030405060708u64.to_be_bytes());
+        body[0x98..0x9C].copy_from_slice(&console_id.to_be_bytes());
+        body[0x9C..0xA4].copy_from_slice(&title_id.to_be_bytes());
+        body[0xB1] = 1;
+        body[0xE2] = 0b1010_0000;
+        body[0x124 + 8..0x128 + 8].copy_from_slice(&4u32.to_be_bytes());
+        body[0x128 + 8..0x12C + 8].copy_from_slice(&30u32.to_be_bytes());
+
+        bytes
+    }
+
+    #[test]
+    fn decodes_ticket() {
+        let ticket = Ticket::from_bytes(&ticket(0x00010001_48414345, 0x0403AC68)).unwrap();
+
+        assert_eq!(ticket.signature.issuer, "Root-CA00000001-XS00000003");
+        assert_eq!(ticket.encrypted_title_key, [0x5A; 16]);
+        assert_eq!(ticket.ticket_id, 0x0102030405060708);
+        assert_eq!(ticket.title_id, 0x00010001_48414345);
+        assert!(ticket.is_personalized());
+        assert_eq!(ticket.common_key_index, 1);
+        assert!(ticket.can_access(0));
+        assert!(!ticket.can_access(1));
+        assert!(ticket.can_access(2));
+        assert!(!ticket.can_access(1000));
+        assert_eq!(ticket.limits, [Limit { kind: 4, value: 30 }]);
+        assert_eq!(ticket.size, 0x140 + BODY_SIZE);
+    }
+
+    #[test]
+    fn parses_consecutive_tickets() {
+        let mut bytes = ticket(0x00010001_48414345, 0);
+        bytes.extend(ticket(0x00010001_48414A45, 0));
+
+        let tickets = parse_tickets(&bytes).unwrap();
+        assert_eq!(tickets.len(), 2);
+        assert!(!tickets[0].is_personalized());
+        assert_eq!(tickets[1].title_id, 0x00010001_48414A45);
+    }
+
+    #[test]
+    fn short_ticket_is_an_error() {
+        let bytes = ticket(0x00010001_48414345, 0);
+
+        assert!(Ticket::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        assert!(parse_tickets(&bytes[..3]).is_err());
+    }
+
+    #[test]
+    fn report_matches_tickets_with_entries() {
+        let entry = |title_id| Entry {
+            title_id,
+            padding: 0,
+            uid: Uid(0x1000),
+        };
+        let entries = [entry(0x00000001_00000002), entry(0x00010001_48414345)];
+        let tickets = parse_tickets(
+            &[
+                ticket(0x00010001_48414345, 0),
+                ticket(0x00010001_48414A45, 0),
+            ]
+            .concat(),
+        )
+        .unwrap();
+
+        let report = ticket_report(&entries, &tickets);
+        assert_eq!(report.missing, [entries[0]]);
+        assert_eq!(report.unused, [tickets[1].clone()]);
+    }
+}
@@ -0,0 +1,110 @@
+use std::fmt::Display;
+
+/// Title ID of the System Menu, always the first entry of uid.sys.
+pub const SYSTEM_MENU_TITLE_ID: u64 = 0x00000001_00000002;
+
+/// The kind of a title, as encoded in the upper half of its title ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TitleType {
+    SystemEssential,
+    VwiiEssential,
+    DiscBasedGame,
+    DownloadedChannel,
+    SystemChannel,
+    VwiiSystemChannel,
+    GameChannel,
+    GameDlc,
+    HiddenChannel,
+    VwiiHidden,
+}
+
+impl TitleType {
+    /// Decodes a title ID prefix. Returns `None` for unknown prefixes.
+    pub fn from_prefix(prefix: u32) -> Option<Self> {
+        Some(match prefix {
+            0x00000001 => Self::SystemEssential,
+            0x00000007 => Self::VwiiEssential,
+            0x00010000 => Self::DiscBasedGame,
+            0x00010001 => Self::DownloadedChannel,
+            0x00010002 => Self::SystemChannel,
+            0x00070002 => Self::VwiiSystemChannel,
+            0x00010004 => Self::GameChannel,
+            0x00010005 => Self::GameDlc,
+            0x00010008 => Self::HiddenChannel,
+            0x00070008 => Self::VwiiHidden,
+
+            _ => return None,
+        })
+    }
+
+    /// The title ID prefix for this kind of title.
+    pub fn prefix(&self) -> u32 {
+        match self {
+            Self::SystemEssential => 0x00000001,
+            Self::VwiiEssential => 0x00000007,
+            Self::DiscBasedGame => 0x00010000,
+            Self::DownloadedChannel => 0x00010001,
+            Self::SystemChannel => 0x00010002,
+            Self::VwiiSystemChannel => 0x00070002,
+            Self::GameChannel => 0x00010004,
+            Self::GameDlc => 0x00010005,
+            Self::HiddenChannel => 0x00010008,
+            Self::VwiiHidden => 0x00070008,
+        }
+    }
+
+    /// A short human-readable description, e.g. `"SYSTEM CHANNEL"`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::SystemEssential => "SYSTEM ESSENTIAL",
+            Self::VwiiEssential => "vWII ESSENTIAL",
+            Self::DiscBasedGame => "DISC-BASED GAME",
+            Self::DownloadedChannel => "DOWNLOADED CHANNEL",
+            Self::SystemChannel => "SYSTEM CHANNEL",
+            Self::VwiiSystemChannel => "vWII SYSTEM CHANNEL",
+            Self::GameChannel => "GAME CHANNEL",
+            Self::GameDlc => "GAME DLC",
+            Self::HiddenChannel => "HIDDEN CHANNEL",
+            Self::VwiiHidden => "vWII HIDDEN",
+        }
+    }
+}
+
+impl Display for TitleType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The IOS number if the title ID is an IOS: a system title with a lower ID
+/// from 3 to 254. Lower IDs 1 and 2 are boot2 and the System Menu.
+pub(crate) fn ios_number(title_id: u64) -> Option<u32> {
+    let lower_id = title_id as u32;
+    (title_id >> 32 == 1 && (3..255).contains(&lower_id)).then_some(lower_id)
+}
+
+/// Renders the lower half of a title ID as ASCII, replacing non-printable
+/// bytes with `.`.
+pub fn make_gameid_string(gameid: u32) -> String {
+    let bytes = gameid.to_be_bytes();
+
+    let mut result = String::new();
+
+    for byte in bytes {
+        let character = if (32..128).contains(&byte) {
+            char::from(byte)
+        } else {
+            '.'
+        };
+
+        result.push(character);
+    }
+
+    result
+}
+
+/// Formats a title ID as its two halves in hexadecimal, e.g.
+/// `00000001-00000002`.
+pub fn format_title_id(title_id: u64) -> String {
+    format!("{:08X}-{:08X}", (title_id >> 32) as u32, title_id as u32)
+}
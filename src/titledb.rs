@@ -0,0 +1,443 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::path::Path;
+
+use quick_xml::events::{BytesStart, Event};
+
+use crate::title::{format_title_id, ios_number, make_gameid_string, TitleType};
+use crate::{Entry, Error};
+
+/// What a title database knows about a title.
+///
+/// Databases in the `ID = Name` text format only provide the name; the
+/// other fields are filled in from GameTDB's wiitdb.xml.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TitleInfo {
+    /// The default name, in English when available.
+    pub name: String,
+    /// Language code of the default name, e.g. `"EN"`, if known.
+    pub language: Option<String>,
+    /// Every localized name as (language code, name) pairs.
+    pub localized_names: Vec<(String, String)>,
+    pub region: Option<String>,
+    pub developer: Option<String>,
+    pub publisher: Option<String>,
+    /// Release date as `YYYY`, `YYYY-MM` or `YYYY-MM-DD`.
+    pub release_date: Option<String>,
+    pub genre: Option<String>,
+}
+
+impl TitleInfo {
+    /// Picks the name in the first of `languages` available, falling back to
+    /// the default name. Returns the name and its language code, if known.
+    /// Language codes are compared case-insensitively.
+    pub fn localized_name(&self, languages: &[String]) -> (&str, Option<&str>) {
+        for wanted in languages {
+            if let Some((lang, name)) = self
+                .localized_names
+                .iter()
+                .find(|(lang, _)| lang.eq_ignore_ascii_case(wanted))
+            {
+                return (name, Some(lang));
+            }
+        }
+
+        (&self.name, self.language.as_deref())
+    }
+
+    /// Whether anything besides the name is known.
+    pub fn has_metadata(&self) -> bool {
+        self.region.is_some()
+            || self.developer.is_some()
+            || self.publisher.is_some()
+            || self.release_date.is_some()
+            || self.genre.is_some()
+    }
+}
+
+/// A mapping from game IDs to what is known about each title.
+#[derive(Debug, Clone, Default)]
+pub struct TitleDb {
+    titles: HashMap<String, TitleInfo>,
+    languages: Vec<String>,
+}
+
+impl TitleDb {
+    /// Reads a title database, either a Wii Title Database text file made
+    /// of `ID = Name` lines or a GameTDB wiitdb.xml file. The format is
+    /// detected from the contents.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let is_xml = reader
+            .fill_buf()?
+            .iter()
+            .find(|b| !b.is_ascii_whitespace())
+            .is_some_and(|&b| b == b'<');
+
+        if is_xml {
+            Self::from_xml(reader)
+        } else {
+            Self::from_reader(reader)
+        }
+    }
+
+    /// Parses a Wii Title Database in the `ID = Name` text format.
+    pub fn from_reader(reader: impl BufRead) -> Result<Self, Error> {
+        let mut titles = HashMap::<String, TitleInfo>::new();
+
+        for line in reader.lines() {
+            let line = line?;
+            let mut entry = line.split(" = ");
+
+            let title_id;
+            let human_name;
+
+            if let (Some(t), Some(h)) = (entry.next(), entry.next()) {
+                title_id = t;
+                human_name = h;
+            } else {
+                return Err(Error::ReadError);
+            }
+
+            titles.insert(
+                title_id.to_owned(),
+                TitleInfo {
+                    name: human_name.to_owned(),
+                    ..Default::default()
+                },
+            );
+        }
+
+        Ok(Self {
+            titles,
+            languages: vec![],
+        })
+    }
+
+    /// Parses a GameTDB wiitdb.xml database.
+    ///
+    /// Titles are indexed by their full ID and, for six-character disc IDs,
+    /// also by the four-character game ID found in uid.sys. The English
+    /// title is used as the name when available.
+    pub fn from_xml(reader: impl BufRead) -> Result<Self, Error> {
+        let mut xml = quick_xml::Reader::from_reader(reader);
+        xml.trim_text(true);
+
+        let mut titles = HashMap::<String, TitleInfo>::new();
+        let mut buf = vec![];
+
+        let mut game: Option<XmlGame> = None;
+        let mut element: Vec<u8> = vec![];
+        let mut locale: Option<String> = None;
+
+        loop {
+            match xml
+                .read_event_into(&mut buf)
+                .map_err(|_| Error::ReadError)?
+            {
+                Event::Eof => break,
+                Event::Start(e) => {
+                    match e.name().as_ref() {
+                        b"game" => game = Some(XmlGame::default()),
+                        b"locale" => locale = attribute(&e, "lang"),
+                        b"date" => {
+                            if let Some(game) = &mut game {
+                                game.info.release_date = date(&e);
+                            }
+                        }
+                        _ => {}
+                    }
+
+                    element = e.name().as_ref().to_owned();
+                }
+                Event::Empty(e) => {
+                    if let (b"date", Some(game)) = (e.name().as_ref(), &mut game) {
+                        game.info.release_date = date(&e);
+                    }
+                }
+                Event::End(e) => {
+                    match e.name().as_ref() {
+                        b"game" => {
+                            if let Some(game) = game.take() {
+                                game.insert_into(&mut titles);
+                            }
+                        }
+                        b"locale" => locale = None,
+                        _ => {}
+                    }
+
+                    element.clear();
+                }
+                Event::Text(t) => {
+                    let Some(game) = &mut game else { continue };
+                    let text = t.unescape().map_err(|_| Error::ReadError)?.into_owned();
+
+                    match (element.as_slice(), &locale) {
+                        (b"id", None) => game.id = text,
+                        (b"region", None) => game.info.region = Some(text),
+                        (b"developer", None) => game.info.developer = Some(text),
+                        (b"publisher", None) => game.info.publisher = Some(text),
+                        (b"genre", None) => game.info.genre = Some(text),
+                        (b"title", Some(lang)) => game.titles.push((lang.clone(), text)),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+
+            buf.clear();
+        }
+
+        Ok(Self {
+            titles,
+            languages: vec![],
+        })
+    }
+
+    /// Sets the preferred languages for names, in order. Titles without a
+    /// name in any of them keep their default name.
+    pub fn with_languages(mut self, languages: Vec<String>) -> Self {
+        self.languages = languages;
+        self
+    }
+
+    /// Looks up a title by game ID.
+    pub fn info(&self, gameid: &str) -> Option<&TitleInfo> {
+        self.titles.get(gameid)
+    }
+
+    /// Looks up a name by game ID, in the preferred language if available.
+    pub fn get(&self, gameid: &str) -> Option<&str> {
+        self.info(gameid)
+            .map(|info| info.localized_name(&self.languages).0)
+    }
+
+    /// Looks up the title of an entry.
+    pub fn info_for(&self, entry: &Entry) -> Option<&TitleInfo> {
+        self.info(&entry.gameid_string())
+    }
+
+    /// The language code of the name [`TitleDb::name_for`] picks for an
+    /// entry, if known.
+    pub fn language_for(&self, entry: &Entry) -> Option<&str> {
+        self.info_for(entry)
+            .and_then(|info| info.localized_name(&self.languages).1)
+    }
+
+    /// Finds a name for an entry. IOS missing from the database, see
+    /// [`Entry::ios_number`], are named as such.
+    pub fn name_for(&self, entry: &Entry) -> Option<String> {
+        self.name_for_title_id(entry.title_id)
+    }
+
+    /// Finds a name for a title ID, as done by [`TitleDb::name_for`].
+    pub fn name_for_title_id(&self, title_id: u64) -> Option<String> {
+        match self.get(&make_gameid_string(title_id as u32)) {
+            Some(s) => Some(s.to_owned()),
+            None => ios_number(title_id).map(|n| format!("IOS {n}")),
+        }
+    }
+}
+
+/// Describes a title in one line: title ID, game ID, title type if known and
+/// name if found in `title_db`, e.g.
+/// `00010001-48414345 (HACE) DOWNLOADED CHANNEL - Mii Channel`.
+pub fn describe_title(title_id: u64, title_db: Option<&TitleDb>) -> String {
+    let mut result = format!(
+        "{} ({})",
+        format_title_id(title_id),
+        make_gameid_string(title_id as u32)
+    );
+
+    if let Some(t) = TitleType::from_prefix((title_id >> 32) as u32) {
+        result.push_str(&format!(" {t}"));
+    }
+
+    if let Some(name) = title_db.and_then(|db| db.name_for_title_id(title_id)) {
+        result.push_str(&format!(" - {name}"));
+    }
+
+    result
+}
+
+/// A `<game>` element being read from wiitdb.xml.
+#[derive(Default)]
+struct XmlGame {
+    id: String,
+    /// Localized titles as (language, title) pairs, in file order.
+    titles: Vec<(String, String)>,
+    info: TitleInfo,
+}
+
+impl XmlGame {
+    fn insert_into(mut self, titles: &mut HashMap<String, TitleInfo>) {
+        let default = self
+            .titles
+            .iter()
+            .find(|(lang, _)| lang == "EN")
+            .or(self.titles.first())
+            .cloned();
+
+        let Some((language, name)) = default else {
+            return;
+        };
+        self.info.name = name;
+        self.info.language = Some(language);
+        self.info.localized_names = self.titles;
+
+        if self.id.len() == 6 {
+            titles
+                .entry(self.id[..4].to_owned())
+                .or_insert_with(|| self.info.clone());
+        }
+
+        titles.insert(self.id, self.info);
+    }
+}
+
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    let value = element.try_get_attribute(name).ok()??;
+    let value = value.unescape_value().ok()?;
+
+    if value.is_empty() {
+        None
+    } else {
+        Some(value.into_owned())
+    }
+}
+
+/// Reads a `<date year=".." month=".." day=".."/>` element.
+fn date(element: &BytesStart) -> Option<String> {
+    let year = attribute(element, "year")?;
+    let month = attribute(element, "month").and_then(|m| m.parse::<u8>().ok());
+    let day = attribute(element, "day").and_then(|d| d.parse::<u8>().ok());
+
+    Some(match (month, day) {
+        (Some(m), Some(d)) => format!("{year}-{m:02}-{d:02}"),
+        (Some(m), None) => format!("{year}-{m:02}"),
+        _ => year,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Uid;
+
+    fn entry(title_id: u64) -> Entry {
+        Entry {
+            title_id,
+            padding: 0,
+            uid: Uid(0x1000),
+        }
+    }
+
+    const WIITDB: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<datafile>
+    <game name="Wii Sports (USA) (EN,FR,ES)">
+        <id>RSPE01</id>
+        <region>NTSC-U</region>
+        <locale lang="FR"><title>Wii Sports FR</title></locale>
+        <locale lang="EN"><title>Wii Sports &amp; More</title></locale>
+        <developer>Nintendo EAD</developer>
+        <publisher>Nintendo</publisher>
+        <date year="2006" month="11" day="19"/>
+        <genre>sports</genre>
+    </game>
+    <game name="Mii Channel">
+        <id>HACE</id>
+        <locale lang="JA"><title>Nigaoe Channel</title></locale>
+        <date year="2006" month="" day=""/>
+    </game>
+    <game name="No titles">
+        <id>HXXE</id>
+    </game>
+</datafile>
+"#;
+
+    #[test]
+    fn parses_wiitdb_xml() {
+        let db = TitleDb::from_xml(WIITDB.as_bytes()).unwrap();
+        let info = db.info("RSPE01").unwrap();
+
+        assert_eq!(info.name, "Wii Sports & More");
+        assert_eq!(info.language.as_deref(), Some("EN"));
+        assert_eq!(info.localized_names.len(), 2);
+        assert_eq!(info.region.as_deref(), Some("NTSC-U"));
+        assert_eq!(info.developer.as_deref(), Some("Nintendo EAD"));
+        assert_eq!(info.publisher.as_deref(), Some("Nintendo"));
+        assert_eq!(info.release_date.as_deref(), Some("2006-11-19"));
+        assert_eq!(info.genre.as_deref(), Some("sports"));
+
+        // Disc IDs are also found by the game ID stored in uid.sys.
+        assert_eq!(db.info("RSPE"), Some(info));
+
+        let mii = db.info("HACE").unwrap();
+        assert_eq!(mii.name, "Nigaoe Channel");
+        assert_eq!(mii.release_date.as_deref(), Some("2006"));
+        assert_eq!(mii.region, None);
+
+        assert_eq!(db.info("HXXE"), None);
+    }
+
+    #[test]
+    fn prefers_requested_languages() {
+        let db = TitleDb::from_xml(WIITDB.as_bytes())
+            .unwrap()
+            .with_languages(vec!["DE".to_owned(), "FR".to_owned()]);
+        let entry = entry(0x00010000_52535045);
+
+        assert_eq!(db.name_for(&entry).as_deref(), Some("Wii Sports FR"));
+        assert_eq!(db.language_for(&entry), Some("FR"));
+        assert_eq!(db.get("HACE"), Some("Nigaoe Channel"));
+    }
+
+    #[test]
+    fn malformed_xml_is_an_error() {
+        let xml = "<datafile><game><id>RSPE01</id></locale></datafile>";
+
+        assert!(matches!(
+            TitleDb::from_xml(xml.as_bytes()),
+            Err(Error::ReadError)
+        ));
+    }
+
+    #[test]
+    fn only_ios_get_a_fallback_name() {
+        let db = TitleDb::from_reader(&b"HACE = Mii Channel\n"[..]).unwrap();
+
+        assert_eq!(
+            db.name_for(&entry(0x00010001_48414345)).as_deref(),
+            Some("Mii Channel")
+        );
+        assert_eq!(
+            db.name_for(&entry(0x00000001_0000003A)).as_deref(),
+            Some("IOS 58")
+        );
+        // boot2, the System Menu and channels with a small lower ID.
+        assert_eq!(db.name_for(&entry(0x00000001_00000001)), None);
+        assert_eq!(db.name_for(&entry(0x00000001_00000002)), None);
+        assert_eq!(db.name_for(&entry(0x00010002_00000005)), None);
+    }
+
+    #[test]
+    fn describes_titles() {
+        let db = TitleDb::from_reader(&b"HACE = Mii Channel\n"[..]).unwrap();
+
+        assert_eq!(
+            describe_title(0x00010001_48414345, Some(&db)),
+            "00010001-48414345 (HACE) DOWNLOADED CHANNEL - Mii Channel"
+        );
+        assert_eq!(
+            describe_title(0x00010001_48414345, None),
+            "00010001-48414345 (HACE) DOWNLOADED CHANNEL"
+        );
+        assert_eq!(
+            describe_title(0x12345678_48414345, Some(&db)),
+            "12345678-48414345 (HACE) - Mii Channel"
+        );
+    }
+}
@@ -0,0 +1,254 @@
+use std::fs;
+use std::path::Path;
+
+use crate::{make_gameid_string, Error, Signature};
+
+/// Size in bytes of a content record in a TMD.
+pub const CONTENT_RECORD_SIZE: usize = 36;
+
+/// Size of the TMD header, from the issuer up to the content records.
+const HEADER_SIZE: usize = 0xA4;
+
+/// A content listed in a TMD.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TmdContent {
+    pub id: u32,
+    pub index: u16,
+    /// Flags; see [`TmdContent::is_shared`].
+    pub content_type: u16,
+    pub size: u64,
+    /// SHA-1 of the decrypted content.
+    pub hash: [u8; 20],
+}
+
+impl TmdContent {
+    /// Whether the content is stored in /shared1 and listed in content.map
+    /// rather than in the title's own directory.
+    pub fn is_shared(&self) -> bool {
+        self.content_type & 0x8000 != 0
+    }
+
+    /// Whether the content may be left out of an installation, as DLC is.
+    pub fn is_optional(&self) -> bool {
+        self.content_type & 0x4000 != 0
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Self {
+        Self {
+            id: u32::from_be_bytes(bytes[0..4].try_into().unwrap()),
+            index: u16::from_be_bytes(bytes[4..6].try_into().unwrap()),
+            content_type: u16::from_be_bytes(bytes[6..8].try_into().unwrap()),
+            size: u64::from_be_bytes(bytes[8..16].try_into().unwrap()),
+            hash: bytes[16..36].try_into().unwrap(),
+        }
+    }
+}
+
+/// A title metadata file (title.tmd), describing an installed title and
+/// its contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tmd {
+    pub signature: Signature,
+    /// Title ID of the IOS the title runs on, or 0 for IOS and boot2.
+    pub sys_version: u64,
+    pub title_id: u64,
+    /// Title type flags, unrelated to the title ID prefix.
+    pub title_type: u32,
+    /// Maker code of the publisher, usually two ASCII characters.
+    pub group_id: u16,
+    /// Region: 0 Japan, 1 USA, 2 Europe, 3 region free, 4 Korea.
+    pub region: u16,
+    /// Hardware access flags; see [`Tmd::has_full_hardware_access`].
+    pub access_rights: u32,
+    pub title_version: u16,
+    /// Index of the content loaded at launch.
+    pub boot_index: u16,
+    pub contents: Vec<TmdContent>,
+}
+
+impl Tmd {
+    /// The lower ID of the IOS the title requires, e.g. 58, or `None` if the
+    /// title does not run on an IOS.
+    pub fn required_ios(&self) -> Option<u32> {
+        if self.sys_version >> 32 == 1 && self.sys_version as u32 != 0 {
+            Some(self.sys_version as u32)
+        } else {
+            None
+        }
+    }
+
+    /// Whether the title is granted direct access to the hardware, bypassing
+    /// IOS (AHBPROT disabled).
+    pub fn has_full_hardware_access(&self) -> bool {
+        self.access_rights & 1 != 0
+    }
+
+    /// Whether the title may read DVD video discs.
+    pub fn has_dvd_video_access(&self) -> bool {
+        self.access_rights & 2 != 0
+    }
+
+    /// The group ID as text, as it is for most titles.
+    pub fn group_id_string(&self) -> String {
+        make_gameid_string(u32::from(self.group_id))[2..].to_owned()
+    }
+
+    /// Decodes a TMD.
+    ///
+    /// Fails with [`Error::Truncated`] if the data is too short for its
+    /// header or content records.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, Error> {
+        let offset = Signature::body_offset(bytes)?;
+        let header = bytes
+            .get(offset..)
+            .filter(|header| header.len() >= HEADER_SIZE)
+            .ok_or(Error::Truncated {
+                offset: 0,
+                trailing: bytes.len(),
+            })?;
+
+        let count = u16::from_be_bytes([header[0x9E], header[0x9F]]) as usize;
+        let records = header
+            .get(HEADER_SIZE..HEADER_SIZE + count * CONTENT_RECORD_SIZE)
+            .ok_or_else(|| {
+                let complete = (header.len() - HEADER_SIZE) / CONTENT_RECORD_SIZE;
+                let record = offset + HEADER_SIZE + complete * CONTENT_RECORD_SIZE;
+
+                Error::Truncated {
+                    offset: record,
+                    trailing: bytes.len() - record,
+                }
+            })?;
+
+        let u16_at = |offset: usize| u16::from_be_bytes([header[offset], header[offset + 1]]);
+        let u32_at =
+            |offset: usize| u32::from_be_bytes(header[offset..offset + 4].try_into().unwrap());
+        let u64_at =
+            |offset: usize| u64::from_be_bytes(header[offset..offset + 8].try_into().unwrap());
+
+        Ok(Self {
+            signature: Signature::read(bytes, HEADER_SIZE + count * CONTENT_RECORD_SIZE)?,
+            sys_version: u64_at(0x44),
+            title_id: u64_at(0x4C),
+            title_type: u32_at(0x54),
+            group_id: u16_at(0x58),
+            region: u16_at(0x5C),
+            access_rights: u32_at(0x98),
+            title_version: u16_at(0x9C),
+            boot_index: u16_at(0xA0),
+            contents: records
+                .chunks_exact(CONTENT_RECORD_SIZE)
+                .map(TmdContent::from_bytes)
+                .collect(),
+        })
+    }
+
+    /// Reads a TMD file.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Error> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+}
+
+/// The path of a title's TMD on the NAND.
+pub fn tmd_path(title_id: u64) -> String {
+    format!(
+        "/title/{:08x}/{:08x}/content/title.tmd",
+        title_id >> 32,
+        title_id as u32
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTENT: TmdContent = TmdContent {
+        id: 0x0000_0009,
+        index: 1,
+        content_type: 0x8001,
+        size: 0x1234,
+        hash: [0xAB; 20],
+    };
+
+    /// An RSA-2048 signed TMD of IOS58's System Menu with the given contents.
+    fn tmd(contents: &[TmdContent]) -> Vec<u8> {
+        let mut bytes = vec![0; 0x140 + HEADER_SIZE];
+        bytes[0..4].copy_from_slice(&0x10001u32.to_be_bytes());
+
+        let header = &mut bytes[0x140..];
+        header[..26].copy_from_slice(b"Root-CA00000001-CP00000004");
+        header[0x44..0x4C].copy_from_slice(&0x00000001_0000003Au64.to_be_bytes());
+        header[0x4C..0x54].copy_from_slice(&0x00000001_00000002u64.to_be_bytes());
+        header[0x54..0x58].copy_from_slice(&1u32.to_be_bytes());
+        header[0x58..0x5A].copy_from_slice(b"01");
+        header[0x5C..0x5E].copy_from_slice(&1u16.to_be_bytes());
+        header[0x98..0x9C].copy_from_slice(&3u32.to_be_bytes());
+        header[0x9C..0x9E].copy_from_slice(&513u16.to_be_bytes());
+        header[0x9E..0xA0].copy_from_slice(&(contents.len() as u16).to_be_bytes());
+        header[0xA0..0xA2].copy_from_slice(&1u16.to_be_bytes());
+
+        for content in contents {
+            bytes.extend(content.id.to_be_bytes());
+            bytes.extend(content.index.to_be_bytes());
+            bytes.extend(content.content_type.to_be_bytes());
+            bytes.extend(content.size.to_be_bytes());
+            bytes.extend(content.hash);
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn decodes_header_and_contents() {
+        let tmd = Tmd::from_bytes(&tmd(&[CONTENT, CONTENT])).unwrap();
+
+        assert_eq!(tmd.signature.issuer, "Root-CA00000001-CP00000004");
+        assert_eq!(tmd.title_id, 0x00000001_00000002);
+        assert_eq!(tmd.required_ios(), Some(58));
+        assert_eq!(tmd.title_type, 1);
+        assert_eq!(tmd.group_id_string(), "01");
+        assert_eq!(tmd.region, 1);
+        assert!(tmd.has_full_hardware_access());
+        assert!(tmd.has_dvd_video_access());
+        assert_eq!(tmd.title_version, 513);
+        assert_eq!(tmd.boot_index, 1);
+        assert_eq!(tmd.contents, [CONTENT, CONTENT]);
+        assert!(tmd.contents[0].is_shared());
+        assert!(!tmd.contents[0].is_optional());
+    }
+
+    #[test]
+    fn truncated_tmd_is_an_error() {
+        // Only the signature type survived.
+        let bytes = tmd(&[])[..8].to_vec();
+        assert!(matches!(
+            Tmd::from_bytes(&bytes),
+            Err(Error::Truncated {
+                offset: 0,
+                trailing: 8
+            })
+        ));
+
+        let bytes = tmd(&[])[..0x150].to_vec();
+        assert!(matches!(
+            Tmd::from_bytes(&bytes),
+            Err(Error::Truncated { offset: 0, .. })
+        ));
+
+        // The second content record is cut short.
+        let bytes = tmd(&[CONTENT, CONTENT]);
+        let end = 0x140 + HEADER_SIZE + CONTENT_RECORD_SIZE;
+        assert!(matches!(
+            Tmd::from_bytes(&bytes[..end + 10]),
+            Err(Error::Truncated { offset, trailing: 10 }) if offset == end
+        ));
+    }
+
+    #[test]
+    fn ios_require_no_ios() {
+        let mut bytes = tmd(&[]);
+        bytes[0x140 + 0x44..0x140 + 0x4C].fill(0);
+
+        assert_eq!(Tmd::from_bytes(&bytes).unwrap().required_ios(), None);
+    }
+}
@@ -0,0 +1,164 @@
+use std::fmt::Display;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::{ticket_path, tmd_path, Entry, Error};
+
+/// How much of a title is present in an extracted NAND.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum InstallState {
+    /// The TMD and at least one content file are present.
+    Installed,
+    /// The title directory exists, e.g. with save data, but the title itself
+    /// is not installed.
+    DataOnly,
+    /// Only a ticket is present.
+    TicketOnly,
+    /// Nothing is left of the title.
+    Ghost,
+}
+
+impl InstallState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            InstallState::Installed => "installed",
+            InstallState::DataOnly => "data-only",
+            InstallState::TicketOnly => "ticket-only",
+            InstallState::Ghost => "ghost",
+        }
+    }
+}
+
+impl Display for InstallState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// An extracted NAND directory tree, such as Dolphin's Wii user directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NandTree {
+    root: PathBuf,
+}
+
+impl NandTree {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self { root: root.into() }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Maps an absolute NAND path, e.g. `/sys/uid.sys`, into the tree.
+    pub fn path(&self, nand_path: &str) -> PathBuf {
+        self.root.join(nand_path.trim_start_matches('/'))
+    }
+
+    /// The directory of a title: `title/<upper>/<lower>`.
+    pub fn title_dir(&self, title_id: u64) -> PathBuf {
+        self.root.join(format!(
+            "title/{:08x}/{:08x}",
+            title_id >> 32,
+            title_id as u32
+        ))
+    }
+
+    /// The path of a title's ticket: `ticket/<upper>/<lower>.tik`.
+    pub fn ticket_path(&self, title_id: u64) -> PathBuf {
+        self.path(&ticket_path(title_id))
+    }
+
+    /// The path of a title's TMD: `title/<upper>/<lower>/content/title.tmd`.
+    pub fn tmd_path(&self, title_id: u64) -> PathBuf {
+        self.path(&tmd_path(title_id))
+    }
+
+    /// Tells how much of a title is present.
+    pub fn install_state(&self, title_id: u64) -> InstallState {
+        let title_dir = self.title_dir(title_id);
+
+        if self.tmd_path(title_id).is_file() && has_content(&title_dir.join("content")) {
+            InstallState::Installed
+        } else if title_dir.is_dir() {
+            InstallState::DataOnly
+        } else if self.ticket_path(title_id).is_file() {
+            InstallState::TicketOnly
+        } else {
+            InstallState::Ghost
+        }
+    }
+
+    /// Lists the title IDs of every directory under `title`, in order.
+    pub fn title_ids(&self) -> Result<Vec<u64>, Error> {
+        self.scan("title", "", true)
+    }
+
+    /// Lists the title IDs of every `.tik` file under `ticket`, in order.
+    pub fn ticket_ids(&self) -> Result<Vec<u64>, Error> {
+        self.scan("ticket", ".tik", false)
+    }
+
+    /// Finds the `<upper>/<lower><suffix>` entries below a directory.
+    fn scan(&self, dir: &str, suffix: &str, want_dir: bool) -> Result<Vec<u64>, Error> {
+        let mut title_ids = vec![];
+
+        for upper in fs::read_dir(self.root.join(dir))? {
+            let upper = upper?;
+            let Some(prefix) = hex_name(&upper.file_name().to_string_lossy()) else {
+                continue;
+            };
+
+            if !upper.file_type()?.is_dir() {
+                continue;
+            }
+
+            for lower in fs::read_dir(upper.path())? {
+                let lower = lower?;
+                let name = lower.file_name().to_string_lossy().into_owned();
+
+                let Some(id) = name.strip_suffix(suffix).and_then(hex_name) else {
+                    continue;
+                };
+
+                if lower.file_type()?.is_dir() == want_dir {
+                    title_ids.push(u64::from(prefix) << 32 | u64::from(id));
+                }
+            }
+        }
+
+        title_ids.sort();
+        Ok(title_ids)
+    }
+
+    /// Lists the titles with a directory on disk but no entry in uid.sys.
+    pub fn missing_from(&self, entries: &[Entry]) -> Result<Vec<u64>, Error> {
+        Ok(self
+            .title_ids()?
+            .into_iter()
+            .filter(|&id| entries.iter().all(|e| e.title_id != id))
+            .collect())
+    }
+}
+
+/// Whether a content directory holds at least one `.app` file.
+fn has_content(dir: &Path) -> bool {
+    let Ok(files) = fs::read_dir(dir) else {
+        return false;
+    };
+
+    files.filter_map(Result::ok).any(|file| {
+        file.path()
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("app"))
+    })
+}
+
+/// Parses a title directory name: eight hexadecimal digits.
+pub(crate) fn hex_name(name: &str) -> Option<u32> {
+    if name.len() != 8 || !name.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+
+    u32::from_str_radix(name, 16).ok()
+}
@@ -0,0 +1,109 @@
+use std::fmt::Display;
+
+/// A user ID as assigned by IOS.
+///
+/// UIDs from 0x1000 upwards are handed out to titles in installation order,
+/// starting with the System Menu. Lower UIDs belong to IOS itself (0 being
+/// root) and should never appear in a healthy uid.sys.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Uid(pub u16);
+
+/// The part of the UID space a [`Uid`] belongs to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UidKind {
+    /// UID 0.
+    Root,
+    /// UIDs 0x0001 to 0x0FFF, reserved for IOS.
+    System,
+    /// UIDs 0x1000 and above, assigned to titles.
+    Title,
+}
+
+impl Uid {
+    pub const ROOT: Uid = Uid(0);
+    /// The first title UID, normally assigned to the System Menu.
+    pub const FIRST_TITLE: Uid = Uid(0x1000);
+
+    pub fn kind(&self) -> UidKind {
+        match self.0 {
+            0 => UidKind::Root,
+            1..=0x0FFF => UidKind::System,
+            _ => UidKind::Title,
+        }
+    }
+
+    /// The position of this UID in the installation order, counting from 1.
+    /// Returns `None` for UIDs outside of the title range.
+    pub fn install_number(&self) -> Option<u16> {
+        self.0.checked_sub(Self::FIRST_TITLE.0).map(|n| n + 1)
+    }
+
+    /// The UID assigned to the `n`th installed title, counting from 1.
+    pub fn from_install_number(n: u16) -> Option<Self> {
+        n.checked_sub(1)
+            .and_then(|n| n.checked_add(Self::FIRST_TITLE.0))
+            .map(Self)
+    }
+}
+
+impl From<u16> for Uid {
+    fn from(value: u16) -> Self {
+        Self(value)
+    }
+}
+
+impl From<Uid> for u16 {
+    fn from(value: Uid) -> Self {
+        value.0
+    }
+}
+
+impl Display for Uid {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:#06X}", self.0)
+    }
+}
+
+impl Display for UidKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            UidKind::Root => "root UID",
+            UidKind::System => "system UID",
+            UidKind::Title => "title UID",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn uids_below_the_title_range_have_no_install_number() {
+        assert_eq!(Uid::ROOT.install_number(), None);
+        assert_eq!(Uid(0x0FFF).install_number(), None);
+        assert_eq!(Uid::FIRST_TITLE.install_number(), Some(1));
+        assert_eq!(Uid(0xFFFF).install_number(), Some(0xF000));
+    }
+
+    #[test]
+    fn install_numbers_round_trip() {
+        assert_eq!(Uid::from_install_number(0), None);
+        assert_eq!(Uid::from_install_number(1), Some(Uid::FIRST_TITLE));
+        assert_eq!(Uid::from_install_number(0xF000), Some(Uid(0xFFFF)));
+        assert_eq!(Uid::from_install_number(0xF001), None);
+
+        for uid in [0x1000, 0x1234, 0xFFFF] {
+            let n = Uid(uid).install_number().unwrap();
+            assert_eq!(Uid::from_install_number(n), Some(Uid(uid)));
+        }
+    }
+
+    #[test]
+    fn kinds() {
+        assert_eq!(Uid::ROOT.kind(), UidKind::Root);
+        assert_eq!(Uid(0x0001).kind(), UidKind::System);
+        assert_eq!(Uid(0x0FFF).kind(), UidKind::System);
+        assert_eq!(Uid::FIRST_TITLE.kind(), UidKind::Title);
+    }
+}
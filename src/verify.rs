@@ -0,0 +1,298 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::{format_title_id, Entry, Truncation, Uid, UidKind, SYSTEM_MENU_TITLE_ID};
+
+/// A structural anomaly found in a uid.sys file.
+///
+/// Entry positions are counted from 1, as in the listing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The file ends with an incomplete record.
+    Truncated(Truncation),
+    /// The file has no entries at all.
+    Empty,
+    /// The first entry is not the System Menu at UID 0x1000.
+    BadFirstEntry { title_id: u64, uid: Uid },
+    /// A title appears more than once.
+    DuplicateTitleId {
+        title_id: u64,
+        first: usize,
+        position: usize,
+    },
+    /// A UID is assigned to more than one title.
+    DuplicateUid {
+        uid: Uid,
+        first: usize,
+        position: usize,
+    },
+    /// A UID is lower than the one of the entry before it.
+    NonSequentialUid {
+        uid: Uid,
+        previous: Uid,
+        position: usize,
+    },
+    /// UIDs are skipped between two consecutive entries.
+    UidGap {
+        previous: Uid,
+        uid: Uid,
+        position: usize,
+    },
+    /// A UID is outside of the title range.
+    ReservedUid { uid: Uid, position: usize },
+    /// The padding bytes of a record are not zero.
+    NonZeroPadding { padding: u16, position: usize },
+    /// A title ID has an unknown prefix.
+    UnknownTitleType { title_id: u64, position: usize },
+}
+
+impl Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Truncated(t) => write!(
+                f,
+                "file has {} trailing bytes at offset {:#X}",
+                t.len, t.offset
+            ),
+            Problem::Empty => write!(f, "file has no entries"),
+            Problem::BadFirstEntry { title_id, uid } => write!(
+                f,
+                "entry 1: expected System Menu at UID {}, found {} at UID {uid}",
+                Uid::FIRST_TITLE,
+                format_title_id(*title_id)
+            ),
+            Problem::DuplicateTitleId {
+                title_id,
+                first,
+                position,
+            } => write!(
+                f,
+                "entry {position}: title {} already listed at entry {first}",
+                format_title_id(*title_id)
+            ),
+            Problem::DuplicateUid {
+                uid,
+                first,
+                position,
+            } => write!(
+                f,
+                "entry {position}: UID {uid} already assigned at entry {first}"
+            ),
+            Problem::NonSequentialUid {
+                uid,
+                previous,
+                position,
+            } => write!(
+                f,
+                "entry {position}: UID {uid} follows higher UID {previous}"
+            ),
+            Problem::UidGap {
+                previous,
+                uid,
+                position,
+            } => write!(
+                f,
+                "entry {position}: {} UIDs missing between {previous} and {uid}",
+                uid.0 - previous.0 - 1
+            ),
+            Problem::ReservedUid { uid, position } => {
+                write!(f, "entry {position}: {uid} is a {}", uid.kind())
+            }
+            Problem::NonZeroPadding { padding, position } => {
+                write!(f, "entry {position}: non-zero padding {padding:#06X}")
+            }
+            Problem::UnknownTitleType { title_id, position } => write!(
+                f,
+                "entry {position}: unknown title type prefix {:08X}",
+                (title_id >> 32) as u32
+            ),
+        }
+    }
+}
+
+/// Checks a list of entries for structural anomalies.
+pub fn verify(entries: &[Entry]) -> Vec<Problem> {
+    let mut problems = vec![];
+
+    match entries.first() {
+        None => problems.push(Problem::Empty),
+        Some(first) => {
+            if first.title_id != SYSTEM_MENU_TITLE_ID || first.uid != Uid::FIRST_TITLE {
+                problems.push(Problem::BadFirstEntry {
+                    title_id: first.title_id,
+                    uid: first.uid,
+                });
+            }
+        }
+    }
+
+    let mut title_ids = HashMap::<u64, usize>::new();
+    let mut uids = HashMap::<Uid, usize>::new();
+    let mut previous: Option<Uid> = None;
+
+    for (i, entry) in entries.iter().enumerate() {
+        let position = i + 1;
+
+        if let Some(&first) = title_ids.get(&entry.title_id) {
+            problems.push(Problem::DuplicateTitleId {
+                title_id: entry.title_id,
+                first,
+                position,
+            });
+        } else {
+            title_ids.insert(entry.title_id, position);
+        }
+
+        if entry.uid.kind() != UidKind::Title {
+            problems.push(Problem::ReservedUid {
+                uid: entry.uid,
+                position,
+            });
+        }
+
+        if let Some(&first) = uids.get(&entry.uid) {
+            problems.push(Problem::DuplicateUid {
+                uid: entry.uid,
+                first,
+                position,
+            });
+        } else {
+            uids.insert(entry.uid, position);
+
+            if let Some(previous) = previous {
+                if entry.uid < previous {
+                    problems.push(Problem::NonSequentialUid {
+                        uid: entry.uid,
+                        previous,
+                        position,
+                    });
+                } else if entry.uid.0 - previous.0 > 1 {
+                    problems.push(Problem::UidGap {
+                        previous,
+                        uid: entry.uid,
+                        position,
+                    });
+                }
+            }
+        }
+
+        previous = Some(entry.uid);
+
+        if entry.padding != 0 {
+            problems.push(Problem::NonZeroPadding {
+                padding: entry.padding,
+                position,
+            });
+        }
+
+        if entry.title_type().is_none() {
+            problems.push(Problem::UnknownTitleType {
+                title_id: entry.title_id,
+                position,
+            });
+        }
+    }
+
+    problems
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(title_id: u64, uid: u16) -> Entry {
+        Entry {
+            title_id,
+            padding: 0,
+            uid: Uid(uid),
+        }
+    }
+
+    #[test]
+    fn well_formed_file_has_no_problems() {
+        let entries = [
+            entry(SYSTEM_MENU_TITLE_ID, 0x1000),
+            entry(0x00000001_0000003A, 0x1001),
+            entry(0x00010001_48414345, 0x1002),
+        ];
+
+        assert_eq!(verify(&entries), []);
+    }
+
+    #[test]
+    fn empty_file() {
+        assert_eq!(verify(&[]), [Problem::Empty]);
+    }
+
+    #[test]
+    fn reports_each_anomaly_with_its_position() {
+        let entries = [
+            entry(0x00000001_0000003A, 0x1000),
+            entry(SYSTEM_MENU_TITLE_ID, 0x1003),
+            entry(0x00000001_0000003A, 0x1002),
+            entry(0x00010001_48414345, 0x1002),
+            Entry {
+                padding: 0xABCD,
+                ..entry(0x12345678_00000000, 0x0010)
+            },
+        ];
+
+        assert_eq!(
+            verify(&entries),
+            [
+                Problem::BadFirstEntry {
+                    title_id: 0x00000001_0000003A,
+                    uid: Uid(0x1000)
+                },
+                Problem::UidGap {
+                    previous: Uid(0x1000),
+                    uid: Uid(0x1003),
+                    position: 2
+                },
+                Problem::DuplicateTitleId {
+                    title_id: 0x00000001_0000003A,
+                    first: 1,
+                    position: 3
+                },
+                Problem::NonSequentialUid {
+                    uid: Uid(0x1002),
+                    previous: Uid(0x1003),
+                    position: 3
+                },
+                Problem::DuplicateUid {
+                    uid: Uid(0x1002),
+                    first: 3,
+                    position: 4
+                },
+                Problem::ReservedUid {
+                    uid: Uid(0x0010),
+                    position: 5
+                },
+                Problem::NonSequentialUid {
+                    uid: Uid(0x0010),
+                    previous: Uid(0x1002),
+                    position: 5
+                },
+                Problem::NonZeroPadding {
+                    padding: 0xABCD,
+                    position: 5
+                },
+                Problem::UnknownTitleType {
+                    title_id: 0x12345678_00000000,
+                    position: 5
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn describes_uid_gaps() {
+        let problem = Problem::UidGap {
+            previous: Uid(0x1000),
+            uid: Uid(0x1003),
+            position: 2,
+        };
+
+        assert!(problem.to_string().starts_with("entry 2: 2 UIDs missing"));
+    }
+}